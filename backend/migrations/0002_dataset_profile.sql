@@ -0,0 +1 @@
+ALTER TABLE datasets ADD COLUMN IF NOT EXISTS profile JSONB;
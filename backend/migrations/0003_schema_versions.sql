@@ -0,0 +1,13 @@
+ALTER TABLE datasets
+    ADD COLUMN IF NOT EXISTS schema_version INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'active',
+    ADD COLUMN IF NOT EXISTS last_synced_at BIGINT;
+
+CREATE TABLE IF NOT EXISTS schema_versions (
+    dataset_id TEXT NOT NULL REFERENCES datasets (id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    schema JSONB NOT NULL,
+    changes JSONB NOT NULL,
+    recorded_at BIGINT NOT NULL,
+    PRIMARY KEY (dataset_id, version)
+);
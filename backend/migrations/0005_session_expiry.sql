@@ -0,0 +1,7 @@
+-- Sessions created before expiry was tracked are expired at once
+ALTER TABLE sessions
+    ADD COLUMN IF NOT EXISTS created_at BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS last_seen_at BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS expires_at BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS sessions_expires_at_idx ON sessions (expires_at);
@@ -0,0 +1,3 @@
+-- User types from before roles
+UPDATE users SET user_type = 'platform_admin' WHERE user_type = 'superadmin';
+UPDATE users SET user_type = 'analyst' WHERE user_type = 'user';
@@ -0,0 +1,3 @@
+-- Connectors saved before this are treated as plain connection strings until
+-- their connection string is saved again
+ALTER TABLE connectors ADD COLUMN IF NOT EXISTS secret_ref BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,306 @@
+use crate::core::{create_id, now, User};
+use crate::data::Database;
+use anyhow::{anyhow, Result};
+use axum::http::Method;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+// Keys look like `ak_<id>_<secret>`; only a hash of the secret is stored
+pub const API_KEY_PREFIX: &str = "ak_";
+// last_used_at is written back at most this often, in seconds
+const LAST_USED_INTERVAL: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Scope {
+    #[serde(rename = "datasets:read")]
+    DatasetsRead,
+    #[serde(rename = "datasets:write")]
+    DatasetsWrite,
+    #[serde(rename = "connectors:admin")]
+    ConnectorsAdmin,
+    #[serde(rename = "query:run")]
+    QueryRun,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub hash: String,
+    pub scopes: Vec<Scope>,
+    // Seconds since the Unix epoch
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+    pub last_used_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Create {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    // Keys without an expiry last until they are revoked
+    pub expires_in_secs: Option<u64>,
+    // Service account to own the key instead of the caller
+    pub service_account_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct List {
+    pub service_account_id: Option<String>,
+}
+
+/// A new key along with its token, which can not be retrieved again.
+#[derive(Debug, Clone, Serialize)]
+pub struct Created {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub token: String,
+}
+
+fn hash_secret(secret: &str) -> String {
+    STANDARD.encode(Sha256::digest(secret.as_bytes()))
+}
+
+// Compares digests in constant time, so timing does not reveal how much of a
+// guessed secret matched
+fn verify_secret(secret: &str, hash: &str) -> bool {
+    let Ok(stored) = STANDARD.decode(hash) else {
+        return false;
+    };
+    Sha256::digest(secret.as_bytes())
+        .as_slice()
+        .ct_eq(&stored)
+        .into()
+}
+
+impl ApiKey {
+    pub async fn create<T: Database>(
+        database: T,
+        owner: &User,
+        payload: &Create,
+    ) -> Result<Created> {
+        if payload.name.trim().is_empty() {
+            return Err(anyhow!("name must not be empty"));
+        }
+        if payload.scopes.is_empty() {
+            return Err(anyhow!("at least one scope is required"));
+        }
+        if payload.expires_in_secs == Some(0) {
+            return Err(anyhow!("expires_in_secs must be positive"));
+        }
+
+        let now = now();
+        let id = create_id(12).await;
+        let secret = create_id(40).await;
+        let mut scopes: Vec<Scope> = Vec::new();
+        for scope in &payload.scopes {
+            if !scopes.contains(scope) {
+                scopes.push(*scope);
+            }
+        }
+        let api_key = ApiKey {
+            id: id.clone(),
+            name: payload.name.trim().to_string(),
+            user_id: owner.id.clone(),
+            hash: hash_secret(&secret),
+            scopes,
+            created_at: now,
+            expires_at: payload.expires_in_secs.map(|secs| now + secs),
+            last_used_at: None,
+        };
+        database.create_api_key(&api_key).await?;
+        Ok(Created {
+            api_key,
+            token: format!("{API_KEY_PREFIX}{id}_{secret}"),
+        })
+    }
+
+    pub async fn from_id<T: Database>(database: T, id: &str) -> Result<Self> {
+        database.get_api_key_by_id(id).await
+    }
+
+    pub async fn for_user<T: Database>(database: T, user_id: &str) -> Result<Vec<Self>> {
+        database.get_api_keys_by_user(user_id).await
+    }
+
+    pub async fn delete<T: Database>(database: T, id: &str) -> Result<()> {
+        database.delete_api_key(id).await
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| now() >= expires_at)
+    }
+
+    /// Finds the key for a bearer token, rejecting unknown, revoked and
+    /// expired keys. Records when the key was used.
+    pub async fn authenticate<T: Database>(database: T, token: &str) -> Result<Self> {
+        let (id, secret) = token
+            .strip_prefix(API_KEY_PREFIX)
+            .and_then(|token| token.split_once('_'))
+            .ok_or_else(|| anyhow!("malformed API key"))?;
+        let mut api_key = database
+            .get_api_key_by_id(id)
+            .await
+            .map_err(|_| anyhow!("unknown API key"))?;
+        if !verify_secret(secret, &api_key.hash) {
+            return Err(anyhow!("unknown API key"));
+        }
+        if api_key.is_expired() {
+            return Err(anyhow!("API key {id} expired"));
+        }
+
+        let now = now();
+        if api_key
+            .last_used_at
+            .is_none_or(|last_used_at| now.saturating_sub(last_used_at) >= LAST_USED_INTERVAL)
+        {
+            api_key.last_used_at = Some(now);
+            database.update_api_key(&api_key).await?;
+        }
+        Ok(api_key)
+    }
+
+    /// Whether the key's scopes allow a request. Keys act as their owner, so
+    /// the owner's own permissions are checked by the route as well.
+    pub fn permits(&self, method: &Method, path: &str) -> bool {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let scope = match (method, segments.as_slice()) {
+            (&Method::GET, ["profile"]) => return true,
+            (&Method::POST, ["datasets", _, "query"]) => Scope::QueryRun,
+            (&Method::GET, ["datasets", ..]) => Scope::DatasetsRead,
+            (_, ["dataset"] | ["datasets", ..]) => Scope::DatasetsWrite,
+            (_, ["connectors", ..]) => Scope::ConnectorsAdmin,
+            // Users, orgs, teams and API keys are managed with a session
+            _ => return false,
+        };
+        self.scopes.contains(&scope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Role;
+    use crate::data::{ApiKeyStore, Memory};
+
+    fn owner() -> User {
+        User {
+            id: String::from("owner"),
+            email: String::from("owner@example.com"),
+            first_name: String::from("Owner"),
+            last_name: String::new(),
+            r#type: Role::ServiceAccount,
+            is_active: true,
+            hash: String::new(),
+        }
+    }
+
+    async fn key(database: Memory, scopes: Vec<Scope>) -> Created {
+        let payload = Create {
+            name: String::from("test"),
+            scopes,
+            expires_in_secs: None,
+            service_account_id: None,
+        };
+        ApiKey::create(database, &owner(), &payload).await.unwrap()
+    }
+
+    #[test]
+    fn permits_by_scope() {
+        let api_key = |scopes: Vec<Scope>| ApiKey {
+            id: String::from("id"),
+            name: String::from("test"),
+            user_id: String::from("owner"),
+            hash: String::new(),
+            scopes,
+            created_at: 0,
+            expires_at: None,
+            last_used_at: None,
+        };
+
+        let reader = api_key(vec![Scope::DatasetsRead, Scope::QueryRun]);
+        assert!(reader.permits(&Method::GET, "/profile"));
+        assert!(reader.permits(&Method::GET, "/datasets"));
+        assert!(reader.permits(&Method::GET, "/datasets/abc/preview"));
+        assert!(reader.permits(&Method::POST, "/datasets/abc/query"));
+        assert!(!reader.permits(&Method::POST, "/connectors/abc/query"));
+        assert!(!reader.permits(&Method::POST, "/dataset"));
+        assert!(!reader.permits(&Method::GET, "/connectors"));
+
+        let admin = api_key(vec![Scope::DatasetsWrite, Scope::ConnectorsAdmin]);
+        assert!(admin.permits(&Method::POST, "/dataset"));
+        assert!(admin.permits(&Method::POST, "/datasets/abc/profile"));
+        assert!(admin.permits(&Method::POST, "/connectors/abc/query"));
+        assert!(!admin.permits(&Method::GET, "/datasets"));
+        // Never with a key, whatever its scopes
+        assert!(!admin.permits(&Method::GET, "/users"));
+        assert!(!admin.permits(&Method::POST, "/apikeys"));
+    }
+
+    #[tokio::test]
+    async fn authenticates_tokens() {
+        let database = Memory::default();
+        let created = key(database.clone(), vec![Scope::DatasetsRead]).await;
+        assert!(created.token.starts_with(API_KEY_PREFIX));
+
+        let api_key = ApiKey::authenticate(database.clone(), &created.token)
+            .await
+            .unwrap();
+        assert_eq!(api_key.id, created.api_key.id);
+        assert!(api_key.last_used_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_and_wrong_tokens() {
+        let database = Memory::default();
+        let created = key(database.clone(), vec![Scope::DatasetsRead]).await;
+        let id = &created.api_key.id;
+
+        for token in [
+            String::new(),
+            String::from("ak_"),
+            String::from("bearer-token"),
+            format!("{API_KEY_PREFIX}{id}"),
+            format!("{API_KEY_PREFIX}{id}_wrong"),
+            format!("{API_KEY_PREFIX}unknown_secret"),
+            created.token.replacen(API_KEY_PREFIX, "xx_", 1),
+        ] {
+            assert!(
+                ApiKey::authenticate(database.clone(), &token)
+                    .await
+                    .is_err(),
+                "{token} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn verifies_secrets_against_their_hash() {
+        let hash = hash_secret("secret");
+        assert!(verify_secret("secret", &hash));
+        assert!(!verify_secret("secreT", &hash));
+        assert!(!verify_secret("secret", "not base64"));
+        assert!(!verify_secret("secret", &hash[..8]));
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_keys() {
+        let database = Memory::default();
+        let created = key(database.clone(), vec![Scope::DatasetsRead]).await;
+        let mut api_key = created.api_key;
+        api_key.expires_at = Some(now() - 1);
+        database.update_api_key(&api_key).await.unwrap();
+
+        let error = ApiKey::authenticate(database, &created.token)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("expired"));
+        assert!(api_key.is_expired());
+    }
+}
@@ -1,17 +1,112 @@
+use crate::core::{create_id, now};
 use crate::core::{
     user::{self, User},
-    Session,
+    ApiKey, Permission, Session, SessionPolicy, API_KEY_PREFIX,
 };
 use crate::data::Database;
 use crate::AppState;
 use axum::{
     extract::{Request, State},
-    http::StatusCode,
+    http::{Method, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use cookie::time::Duration;
 use tracing::info;
 
+// Cookie holding the session id for browsers
+pub const SESSION_COOKIE: &str = "sid";
+// Readable by the UI, which sends it back in `CSRF_HEADER` on mutating requests
+pub const CSRF_COOKIE: &str = "csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Adds the session and CSRF cookies for a new session. They last until the
+/// session's maximum age; idle expiry is enforced by `auth`. They are Lax so
+/// they are sent when a single sign-on login redirects back to the UI.
+pub async fn add_session_cookies(
+    jar: CookieJar,
+    session: &Session,
+    policy: &SessionPolicy,
+) -> CookieJar {
+    let remaining = policy.max_expires_at(session).saturating_sub(now());
+    let max_age = Duration::seconds(i64::try_from(remaining).unwrap_or(i64::MAX));
+    let session_cookie = Cookie::build((SESSION_COOKIE, session.id.clone()))
+        .path("/")
+        .http_only(true)
+        .secure(policy.secure_cookies)
+        .same_site(SameSite::Lax)
+        .max_age(max_age);
+    let csrf_cookie = Cookie::build((CSRF_COOKIE, create_id(30).await))
+        .path("/")
+        .secure(policy.secure_cookies)
+        .same_site(SameSite::Lax)
+        .max_age(max_age);
+    jar.add(session_cookie).add(csrf_cookie)
+}
+
+/// Clears the cookies added by `add_session_cookies`.
+pub fn remove_session_cookies(jar: CookieJar) -> CookieJar {
+    jar.remove(Cookie::build(SESSION_COOKIE).path("/"))
+        .remove(Cookie::build(CSRF_COOKIE).path("/"))
+}
+
+// Double-submit check: a cross-site page can make the browser send the
+// cookies, but can not read the CSRF cookie to repeat it in a header
+fn csrf_valid(request: &Request, jar: &CookieJar) -> bool {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return true;
+    }
+    let Some(cookie) = jar.get(CSRF_COOKIE) else {
+        return false;
+    };
+    request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| !value.is_empty() && value == cookie.value())
+}
+
+// API keys act as their owner, limited to the key's scopes
+async fn authenticate_api_key<D: Database>(
+    state: AppState<D>,
+    mut request: Request,
+    next: Next,
+    token: &str,
+) -> Response {
+    let api_key = match ApiKey::authenticate(state.db.clone(), token).await {
+        Ok(api_key) => api_key,
+        Err(e) => {
+            info!("API key rejected: {e}");
+            return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED").into_response();
+        }
+    };
+    if !api_key.permits(request.method(), request.uri().path()) {
+        info!(
+            "API key {} lacks the scope for {}",
+            api_key.id,
+            request.uri().path()
+        );
+        return (StatusCode::FORBIDDEN, "FORBIDDEN").into_response();
+    }
+    let user = match User::from_id(state.db, &api_key.user_id).await {
+        Ok(user) if user.is_active => user,
+        _ => {
+            info!("API key owner not found or inactive");
+            return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED").into_response();
+        }
+    };
+
+    request.extensions_mut().insert(api_key);
+    request
+        .extensions_mut()
+        .insert(user::Extension { user: Some(user) });
+    next.run(request).await
+}
+
 pub async fn auth<D: Database>(
     State(state): State<AppState<D>>,
     mut request: Request,
@@ -28,21 +123,40 @@ pub async fn auth<D: Database>(
 
     let auth_header_value = request.headers().get("Authorization");
 
+    // API clients send a bearer token, browsers the session cookie
     let token = if let Some(value) = auth_header_value {
         let value = value.to_str().unwrap();
+        if let Some(token) = value
+            .strip_prefix("Bearer ")
+            .filter(|token| token.starts_with(API_KEY_PREFIX))
+        {
+            let token = token.to_string();
+            return authenticate_api_key(state, request, next, &token).await;
+        }
         if value.starts_with("Bearer ") {
-            Some(value.trim_start_matches("Bearer ").to_string())
+            value.trim_start_matches("Bearer ").to_string()
         } else {
             return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED").into_response();
         }
     } else {
-        return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED").into_response();
+        let jar = CookieJar::from_headers(request.headers());
+        let Some(cookie) = jar.get(SESSION_COOKIE) else {
+            return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED").into_response();
+        };
+        if !csrf_valid(&request, &jar) {
+            info!("CSRF token missing or mismatched");
+            return (StatusCode::FORBIDDEN, "FORBIDDEN").into_response();
+        }
+        cookie.value().to_string()
     };
 
-    let token = token.unwrap();
-    let Ok(session) = Session::from_id(state.db.clone(), &token).await else {
+    let Ok(mut session) = Session::from_id(state.db.clone(), &token).await else {
         return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED").into_response();
     };
+    if let Err(e) = session.renew(state.db.clone(), &state.session_policy).await {
+        info!("Session rejected: {e}");
+        return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED").into_response();
+    }
 
     request.extensions_mut().insert(session.clone());
     if let Some(user_id) = session.user_id {
@@ -70,3 +184,24 @@ pub async fn auth<D: Database>(
     let response = next.run(request).await;
     response.into_response()
 }
+
+/// Rejects requests whose user's role lacks `permission`. Layered on routes
+/// inside `auth`, e.g. `from_fn_with_state(Permission::TeamsAdmin, require)`.
+/// Anonymous sessions, which `auth` only lets read, may read datasets.
+pub async fn require(
+    State(permission): State<Permission>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match request.extensions().get::<user::Extension>() {
+        Some(user::Extension { user: Some(user) }) => {
+            if !user.r#type.permits(permission) {
+                info!("User {} ({}) lacks {permission:?}", user.id, user.r#type);
+                return (StatusCode::FORBIDDEN, "FORBIDDEN").into_response();
+            }
+        }
+        Some(user::Extension { user: None }) if permission == Permission::DatasetsRead => {}
+        _ => return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED").into_response(),
+    }
+    next.run(request).await
+}
@@ -1,4 +1,5 @@
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub async fn create_id(length: u64) -> String {
     let code: String = (0..length)
@@ -6,3 +7,10 @@ pub async fn create_id(length: u64) -> String {
         .collect();
     code.to_uppercase()
 }
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,309 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fmt;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::warn;
+
+// Encrypted values are "enc:v1:{key id}:{wrapped data key}:{ciphertext}"
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// Key that wraps the per-value data keys. Identified by a hash of the key.
+#[derive(Clone)]
+pub struct MasterKey {
+    pub id: String,
+    cipher: Aes256Gcm,
+}
+
+impl Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MasterKey").field("id", &self.id).finish()
+    }
+}
+
+impl MasterKey {
+    /// Reads a base64 encoded 256-bit key.
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        let bytes = STANDARD.decode(encoded.trim())?;
+        if bytes.len() != 32 {
+            return Err(anyhow!("Master key must be 32 bytes, got {}", bytes.len()));
+        }
+        let digest = Sha256::digest(&bytes);
+        Ok(MasterKey {
+            id: digest[..4].iter().map(|b| format!("{b:02x}")).collect(),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)),
+        })
+    }
+}
+
+/// Source of master keys. The first key encrypts new values, any key may decrypt.
+pub trait KeyProvider: Debug + Send + Sync {
+    fn keys(&self) -> Result<Vec<MasterKey>>;
+}
+
+/// Comma separated keys in an environment variable.
+#[derive(Debug, Clone)]
+pub struct EnvKeys {
+    pub var: String,
+}
+
+impl KeyProvider for EnvKeys {
+    fn keys(&self) -> Result<Vec<MasterKey>> {
+        env::var(&self.var)?
+            .split(',')
+            .filter(|key| !key.trim().is_empty())
+            .map(MasterKey::from_base64)
+            .collect()
+    }
+}
+
+/// One key per line in a file. Lines starting with '#' are ignored. The file is
+/// read on every use, so keys can be rotated without a restart.
+#[derive(Debug, Clone)]
+pub struct FileKeys {
+    pub path: PathBuf,
+}
+
+impl KeyProvider for FileKeys {
+    fn keys(&self) -> Result<Vec<MasterKey>> {
+        std::fs::read_to_string(&self.path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(MasterKey::from_base64)
+            .collect()
+    }
+}
+
+/// Envelope encryption of connector secrets. Each value is encrypted with its
+/// own data key, which is stored alongside it wrapped by a master key.
+#[derive(Debug, Clone, Default)]
+pub struct Envelope {
+    // Values are stored as plaintext when no keys are configured
+    provider: Option<Arc<dyn KeyProvider>>,
+}
+
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    Ok(STANDARD.encode([nonce.as_slice(), &ciphertext].concat()))
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &str) -> Result<Vec<u8>> {
+    let bytes = STANDARD.decode(sealed)?;
+    if bytes.len() < NONCE_LEN {
+        return Err(anyhow!("Encrypted value is truncated"));
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Decryption failed"))
+}
+
+impl Envelope {
+    pub fn new(provider: Arc<dyn KeyProvider>) -> Self {
+        Envelope {
+            provider: Some(provider),
+        }
+    }
+
+    /// Uses the keys in `MASTER_KEY_FILE`, or else in `MASTER_KEY`.
+    pub fn from_env() -> Result<Self> {
+        let envelope = if let Ok(path) = env::var("MASTER_KEY_FILE") {
+            Envelope::new(Arc::new(FileKeys { path: path.into() }))
+        } else if env::var("MASTER_KEY").is_ok() {
+            Envelope::new(Arc::new(EnvKeys {
+                var: String::from("MASTER_KEY"),
+            }))
+        } else {
+            warn!("no master key configured, connection strings are stored unencrypted.");
+            return Ok(Envelope { provider: None });
+        };
+        // Fail at startup rather than on first use
+        envelope.current_key()?;
+        Ok(envelope)
+    }
+
+    fn keys(&self) -> Result<Option<Vec<MasterKey>>> {
+        self.provider
+            .as_ref()
+            .map(|provider| provider.keys())
+            .transpose()
+    }
+
+    fn current_key(&self) -> Result<Option<MasterKey>> {
+        match self.keys()? {
+            Some(keys) => Ok(Some(
+                keys.into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!("No master key configured"))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    // Splits an encrypted value into its master key, wrapped data key and ciphertext
+    fn parse<'a>(&self, value: &'a str) -> Result<(MasterKey, &'a str, &'a str)> {
+        let parts: Vec<&str> = value
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| anyhow!("Value is not encrypted"))?
+            .split(':')
+            .collect();
+        let [key_id, wrapped_key, ciphertext] = parts[..] else {
+            return Err(anyhow!("Malformed encrypted value"));
+        };
+        let key = self
+            .keys()?
+            .ok_or_else(|| anyhow!("Value is encrypted but no master key is configured"))?
+            .into_iter()
+            .find(|key| key.id == key_id)
+            .ok_or_else(|| anyhow!("Master key {key_id} is not configured"))?;
+        Ok((key, wrapped_key, ciphertext))
+    }
+
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(PREFIX)
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let Some(key) = self.current_key()? else {
+            return Ok(plaintext.to_string());
+        };
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let data_cipher = Aes256Gcm::new(&data_key);
+        Ok(format!(
+            "{PREFIX}{}:{}:{}",
+            key.id,
+            seal(&key.cipher, data_key.as_slice())?,
+            seal(&data_cipher, plaintext.as_bytes())?
+        ))
+    }
+
+    /// Decrypts a value. Values stored before encryption was enabled are returned as is.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        if !Envelope::is_encrypted(value) {
+            return Ok(value.to_string());
+        }
+        let (key, wrapped_key, ciphertext) = self.parse(value)?;
+        let data_key = open(&key.cipher, wrapped_key)?;
+        let data_cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key));
+        Ok(String::from_utf8(open(&data_cipher, ciphertext)?)?)
+    }
+
+    /// Wraps a value's data key with the current master key, encrypting
+    /// plaintext values. Returns `None` when the value is already current.
+    pub fn reencrypt(&self, value: &str) -> Result<Option<String>> {
+        let Some(current) = self.current_key()? else {
+            return Ok(None);
+        };
+        if !Envelope::is_encrypted(value) {
+            return self.encrypt(value).map(Some);
+        }
+        let (key, wrapped_key, ciphertext) = self.parse(value)?;
+        if key.id == current.id {
+            return Ok(None);
+        }
+        // The ciphertext is kept, only its data key is wrapped again
+        let data_key = open(&key.cipher, wrapped_key)?;
+        Ok(Some(format!(
+            "{PREFIX}{}:{}:{ciphertext}",
+            current.id,
+            seal(&current.cipher, &data_key)?
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Keys(Vec<String>);
+
+    impl KeyProvider for Keys {
+        fn keys(&self) -> Result<Vec<MasterKey>> {
+            self.0
+                .iter()
+                .map(|key| MasterKey::from_base64(key))
+                .collect()
+        }
+    }
+
+    fn key(byte: u8) -> String {
+        STANDARD.encode([byte; 32])
+    }
+
+    fn envelope(keys: &[String]) -> Envelope {
+        Envelope::new(Arc::new(Keys(keys.to_vec())))
+    }
+
+    #[test]
+    fn round_trips() {
+        let envelope = envelope(&[key(1)]);
+        let encrypted = envelope.encrypt("postgres://user:pw@host/db").unwrap();
+        assert!(Envelope::is_encrypted(&encrypted));
+        assert!(!encrypted.contains("pw@host"));
+        assert_eq!(
+            envelope.decrypt(&encrypted).unwrap(),
+            "postgres://user:pw@host/db"
+        );
+        // Each value has its own data key and nonce
+        assert_ne!(
+            envelope.encrypt("same").unwrap(),
+            envelope.encrypt("same").unwrap()
+        );
+    }
+
+    #[test]
+    fn passes_plaintext_through() {
+        let envelope = envelope(&[key(1)]);
+        assert_eq!(envelope.decrypt("sqlite:///db").unwrap(), "sqlite:///db");
+
+        let unconfigured = Envelope { provider: None };
+        assert_eq!(
+            unconfigured.encrypt("sqlite:///db").unwrap(),
+            "sqlite:///db"
+        );
+        assert_eq!(unconfigured.reencrypt("sqlite:///db").unwrap(), None);
+    }
+
+    #[test]
+    fn reencrypts_with_the_current_key() {
+        let old = envelope(&[key(1)]);
+        let encrypted = old.encrypt("secret").unwrap();
+
+        let rotated = envelope(&[key(2), key(1)]);
+        assert_eq!(rotated.decrypt(&encrypted).unwrap(), "secret");
+        let reencrypted = rotated.reencrypt(&encrypted).unwrap().unwrap();
+        assert_eq!(rotated.reencrypt(&reencrypted).unwrap(), None);
+
+        // The old key is no longer needed
+        let current = envelope(&[key(2)]);
+        assert_eq!(current.decrypt(&reencrypted).unwrap(), "secret");
+        assert!(current.decrypt(&encrypted).is_err());
+
+        let plaintext = current.reencrypt("secret").unwrap().unwrap();
+        assert_eq!(current.decrypt(&plaintext).unwrap(), "secret");
+    }
+
+    #[test]
+    fn rejects_tampered_values() {
+        let envelope = envelope(&[key(1)]);
+        let encrypted = envelope.encrypt("secret").unwrap();
+        let (rest, ciphertext) = encrypted.rsplit_once(':').unwrap();
+        let mut bytes = STANDARD.decode(ciphertext).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = format!("{rest}:{}", STANDARD.encode(bytes));
+        assert!(envelope.decrypt(&tampered).is_err());
+        assert!(envelope.decrypt("enc:v1:malformed").is_err());
+    }
+}
@@ -0,0 +1,723 @@
+use crate::core::common::create_id;
+use crate::core::connector;
+use crate::core::profile::Profile;
+use crate::core::query::Dialect;
+use crate::core::schema::{Column as SchemaColumn, Schema};
+use crate::core::sqliteconnector::{self, bind_value, SqliteDialect};
+use crate::data::Database;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde_json::{Map, Number, Value};
+use sqlx::sqlite::{SqliteArguments, SqlitePoolOptions};
+use sqlx::{Executor, SqlitePool};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+/// Flat files listed by a storage backend. Files are loaded into an in-memory
+/// SQLite database on first use so previews and queries run in-process.
+#[derive(Debug, Clone)]
+pub struct FileConnector<S: Storage> {
+    pub storage: S,
+    pub pool: SqlitePool,
+    // Dataset path -> versions of the files it was loaded from
+    loaded: Arc<Mutex<HashMap<String, Vec<String>>>>,
+    pub statement_timeout: Duration,
+    // Larger files are refused rather than loaded into memory
+    pub max_file_size: u64,
+}
+
+/// A file below a storage root.
+#[derive(Debug, Clone)]
+pub struct Object {
+    // Path relative to the root, separated by '/'
+    pub key: String,
+    // Changes whenever the file content does
+    pub version: String,
+    // In bytes
+    pub size: u64,
+}
+
+/// Where a file connector reads its files from.
+#[async_trait]
+pub trait Storage: Debug + Clone + Send + Sync + 'static {
+    async fn list(&self) -> Result<Vec<Object>>;
+    // Local path holding the listed version of an object
+    async fn fetch(&self, object: &Object) -> Result<PathBuf>;
+    // Local path of an object if it can be read without fetching it
+    fn cached(&self, object: &Object) -> Option<PathBuf>;
+}
+
+/// Directory on the local file system.
+#[derive(Debug, Clone)]
+pub struct LocalFiles {
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    NdJson,
+    Parquet,
+}
+
+#[derive(Debug, Clone)]
+struct DataFile {
+    object: Object,
+    // Column values taken from `key=value` folder names
+    partitions: Vec<(String, String)>,
+}
+
+/// A discovered dataset: a single file or a partitioned folder.
+#[derive(Debug, Clone)]
+struct Source {
+    path: String,
+    kind: connector::Kind,
+    format: Format,
+    files: Vec<DataFile>,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    columns: Vec<String>,
+    // Columns a Parquet schema declares as required
+    required: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+// Rows read to infer a schema without loading the whole file
+const INFER_ROWS: usize = 1000;
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "ndjson" | "jsonl" => Some(Format::NdJson),
+            "parquet" => Some(Format::Parquet),
+            _ => None,
+        }
+    }
+}
+
+// Hidden files and markers such as _SUCCESS are not data
+fn is_ignored(key: &str) -> bool {
+    key.split('/')
+        .any(|part| part.starts_with('.') || part.starts_with('_'))
+}
+
+fn partition(part: &str) -> Option<(String, String)> {
+    let (key, value) = part.split_once('=')?;
+    Some((key.to_string(), value.to_string()))
+}
+
+// Groups listed files into datasets. A folder with `key=value` subfolders is a
+// single partitioned dataset of every file below it in the format of its first
+// file, other files are datasets of their own.
+fn discover(mut objects: Vec<Object>) -> Vec<Source> {
+    objects.retain(|object| {
+        !is_ignored(&object.key) && Format::from_path(Path::new(&object.key)).is_some()
+    });
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut folders = BTreeSet::new();
+    for object in &objects {
+        let parts: Vec<&str> = object.key.split('/').collect();
+        if let Some(index) = (1..parts.len() - 1).find(|&index| parts[index].contains('=')) {
+            folders.insert(parts[..index].join("/"));
+        }
+    }
+
+    let mut sources: Vec<Source> = Vec::new();
+    for object in objects {
+        let format = Format::from_path(Path::new(&object.key)).expect("filtered by format");
+        // Sorted order puts a folder before the folders nested in it
+        let Some(folder) = folders
+            .iter()
+            .find(|folder| object.key.starts_with(&format!("{folder}/")))
+        else {
+            sources.push(Source {
+                path: object.key.clone(),
+                kind: connector::Kind::File,
+                format,
+                files: vec![DataFile {
+                    object,
+                    partitions: Vec::new(),
+                }],
+            });
+            continue;
+        };
+
+        let partitions = object.key[folder.len() + 1..]
+            .split('/')
+            .filter_map(partition)
+            .collect();
+        let file = DataFile { object, partitions };
+        match sources.iter_mut().find(|source| &source.path == folder) {
+            Some(source) if source.format == format => source.files.push(file),
+            Some(_) => {}
+            None => sources.push(Source {
+                path: folder.clone(),
+                kind: connector::Kind::PartitionedFolder,
+                format,
+                files: vec![file],
+            }),
+        }
+    }
+    sources.sort_by(|a, b| a.path.cmp(&b.path));
+    sources
+}
+
+fn walk(root: &Path, dir: &Path, objects: &mut Vec<Object>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        // Symbolic links are skipped, they could point outside the root or
+        // back at a parent directory
+        let metadata = entry.path().symlink_metadata()?;
+        if metadata.is_dir() {
+            walk(root, &entry.path(), objects)?;
+            continue;
+        }
+        if !metadata.is_file() {
+            continue;
+        }
+        let key = entry
+            .path()
+            .strip_prefix(root)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        objects.push(Object {
+            key,
+            version: format!("{}-{}", modified.as_nanos(), metadata.len()),
+            size: metadata.len(),
+        });
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Storage for LocalFiles {
+    async fn list(&self) -> Result<Vec<Object>> {
+        let root = self.root.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut objects = Vec::new();
+            walk(&root, &root, &mut objects)?;
+            Ok(objects)
+        })
+        .await?
+    }
+
+    async fn fetch(&self, object: &Object) -> Result<PathBuf> {
+        self.cached(object)
+            .ok_or_else(|| anyhow!("'{}' is not a file below the root", object.key))
+    }
+
+    // The file may have been replaced by a link since it was listed
+    fn cached(&self, object: &Object) -> Option<PathBuf> {
+        let root = self.root.canonicalize().ok()?;
+        let path = root.join(&object.key).canonicalize().ok()?;
+        (path.starts_with(&root) && path.is_file()).then_some(path)
+    }
+}
+
+// CSV fields are untyped, so numbers and booleans are recognised by their text
+fn parse_text(text: &str) -> Value {
+    if text.is_empty() {
+        Value::Null
+    } else if let Ok(i) = text.parse::<i64>() {
+        Value::from(i)
+    } else if let Some(n) = text.parse::<f64>().ok().and_then(Number::from_f64) {
+        Value::Number(n)
+    } else if text.eq_ignore_ascii_case("true") || text.eq_ignore_ascii_case("false") {
+        Value::Bool(text.eq_ignore_ascii_case("true"))
+    } else {
+        Value::String(text.to_string())
+    }
+}
+
+fn read_csv(path: &Path, limit: usize) -> Result<Table> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let columns = reader.headers()?.iter().map(String::from).collect();
+    let mut rows = Vec::new();
+    for record in reader.records().take(limit) {
+        rows.push(record?.iter().map(parse_text).collect());
+    }
+    Ok(Table {
+        columns,
+        required: Vec::new(),
+        rows,
+    })
+}
+
+fn read_ndjson(path: &Path, limit: usize) -> Result<Table> {
+    let mut columns: Vec<String> = Vec::new();
+    let mut objects = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        if objects.len() >= limit {
+            break;
+        }
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: Map<String, Value> = serde_json::from_str(&line)?;
+        for key in object.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+        objects.push(object);
+    }
+
+    let rows = objects
+        .into_iter()
+        .map(|mut object| {
+            columns
+                .iter()
+                .map(|column| object.remove(column).unwrap_or(Value::Null))
+                .collect()
+        })
+        .collect();
+    Ok(Table {
+        columns,
+        required: Vec::new(),
+        rows,
+    })
+}
+
+fn read_parquet(path: &Path, limit: usize) -> Result<Table> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    let schema = reader.metadata().file_metadata().schema();
+    let fields = schema.get_fields();
+    let columns = fields
+        .iter()
+        .map(|field| field.name().to_string())
+        .collect();
+    let required = fields
+        .iter()
+        .filter(|field| !field.is_optional())
+        .map(|field| field.name().to_string())
+        .collect();
+
+    let mut rows = Vec::new();
+    for row in reader.get_row_iter(None)?.take(limit) {
+        rows.push(
+            row?.get_column_iter()
+                .map(|(_, field)| field.to_json_value())
+                .collect(),
+        );
+    }
+    Ok(Table {
+        columns,
+        required,
+        rows,
+    })
+}
+
+fn parquet_rows(path: &Path) -> Result<u64> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    Ok(reader.metadata().file_metadata().num_rows().max(0) as u64)
+}
+
+fn read_file(path: &Path, format: Format, limit: usize) -> Result<Table> {
+    match format {
+        Format::Csv => read_csv(path, limit),
+        Format::NdJson => read_ndjson(path, limit),
+        Format::Parquet => read_parquet(path, limit),
+    }
+}
+
+// Adds the rows of one file to a table, with the file's partition columns
+fn append(table: &mut Table, part: Table, partitions: &[(String, String)]) {
+    let mut columns = part.columns;
+    let mut rows = part.rows;
+    for (key, value) in partitions {
+        if !columns.contains(key) {
+            columns.push(key.clone());
+            rows.iter_mut().for_each(|row| row.push(parse_text(value)));
+        }
+    }
+
+    for column in &columns {
+        if !table.columns.contains(column) {
+            table.columns.push(column.clone());
+        }
+    }
+    for column in part.required {
+        if !table.required.contains(&column) {
+            table.required.push(column);
+        }
+    }
+    // Files may have different columns, so rows follow the combined column order
+    let positions: Vec<Option<usize>> = table
+        .columns
+        .iter()
+        .map(|column| columns.iter().position(|c| c == column))
+        .collect();
+    for row in rows {
+        table.rows.push(
+            positions
+                .iter()
+                .map(|position| position.map_or(Value::Null, |p| row[p].clone()))
+                .collect(),
+        );
+    }
+}
+
+fn value_type(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some("boolean"),
+        Value::Number(n) if n.is_i64() || n.is_u64() => Some("integer"),
+        Value::Number(_) => Some("real"),
+        _ => Some("text"),
+    }
+}
+
+// Narrowest type that holds every value of a column
+fn infer_type(table: &Table, index: usize) -> &'static str {
+    table
+        .rows
+        .iter()
+        .filter_map(|row| row.get(index).and_then(value_type))
+        .reduce(|a, b| match (a, b) {
+            _ if a == b => a,
+            ("integer", "real") | ("real", "integer") => "real",
+            _ => "text",
+        })
+        .unwrap_or("text")
+}
+
+fn infer_schema(table: &Table) -> Schema {
+    let columns = table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(index, name)| SchemaColumn {
+            nullable: !table.required.contains(name),
+            ..SchemaColumn::new(name, infer_type(table, index))
+        })
+        .collect();
+    Schema { columns }
+}
+
+impl<S: Storage> FileConnector<S> {
+    pub async fn connect(
+        storage: S,
+        statement_timeout: Duration,
+        max_file_size: u64,
+    ) -> Result<Self> {
+        // Every table lives in one in-memory database, so the pool keeps a
+        // single connection open for the connector's lifetime
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .acquire_timeout(connector::STATEMENT_TIMEOUT)
+            .connect("sqlite::memory:")
+            .await?;
+        Ok(FileConnector {
+            storage,
+            pool,
+            loaded: Arc::new(Mutex::new(HashMap::new())),
+            statement_timeout,
+            max_file_size,
+        })
+    }
+
+    async fn sources(&self) -> Result<Vec<Source>> {
+        Ok(discover(self.storage.list().await?))
+    }
+
+    async fn source(&self, path: &str) -> Result<Source> {
+        self.sources()
+            .await?
+            .into_iter()
+            .find(|source| source.path == path)
+            .ok_or_else(|| connector::NotFound(path.to_string()).into())
+    }
+
+    // Reads up to `limit` rows across the files of a source, fetching only the files it needs
+    async fn read(&self, source: &Source, limit: usize) -> Result<Table> {
+        let mut table = Table::default();
+        for file in &source.files {
+            if table.rows.len() >= limit {
+                break;
+            }
+            let remaining = limit - table.rows.len();
+            if file.object.size > self.max_file_size {
+                return Err(anyhow!(
+                    "'{}' is {} bytes, larger than the connector's limit of {} bytes",
+                    file.object.key,
+                    file.object.size,
+                    self.max_file_size
+                ));
+            }
+            let path = self.storage.fetch(&file.object).await?;
+            let format = source.format;
+            let part =
+                tokio::task::spawn_blocking(move || read_file(&path, format, remaining)).await??;
+            append(&mut table, part, &file.partitions);
+        }
+        Ok(table)
+    }
+
+    // Loads a source into its SQLite table unless the files are unchanged
+    async fn load(&self, source: Source) -> Result<()> {
+        let mut loaded = self.loaded.lock().await;
+        let table = SqliteDialect.quote_identifier(&source.path);
+        let versions: Vec<String> = source
+            .files
+            .iter()
+            .map(|file| format!("{}:{}", file.object.key, file.object.version))
+            .collect();
+        let exists: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")
+                .bind(&source.path)
+                .fetch_optional(&self.pool)
+                .await?;
+        if exists.is_some() && loaded.get(&source.path) == Some(&versions) {
+            return Ok(());
+        }
+
+        let data = self.read(&source, usize::MAX).await?;
+        let schema = infer_schema(&data);
+        let definitions = schema
+            .columns
+            .iter()
+            .map(|column| {
+                format!(
+                    "{} {}",
+                    SqliteDialect.quote_identifier(&column.name),
+                    column.data_type
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let placeholders = (1..=schema.columns.len())
+            .map(|index| SqliteDialect.placeholder(index))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let mut tx = self.pool.begin().await?;
+        tx.execute(format!("DROP TABLE IF EXISTS {table}").as_str())
+            .await?;
+        tx.execute(format!("CREATE TABLE {table} ({definitions})").as_str())
+            .await?;
+        let insert = format!("INSERT INTO {table} VALUES ({placeholders})");
+        for row in &data.rows {
+            let mut arguments = SqliteArguments::default();
+            row.iter()
+                .for_each(|value| bind_value(&mut arguments, value));
+            sqlx::query_with(&insert, arguments)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        loaded.insert(source.path, versions);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S: Storage> connector::Trait for FileConnector<S> {
+    async fn create_record<D: Database>(
+        database: D,
+        conn: connector::Create,
+    ) -> Result<connector::Details> {
+        let id = create_id(8).await;
+        let connector_details = connector::Details {
+            id,
+            name: conn.name,
+            r#type: conn.r#type,
+            connection_string: conn.connection_string,
+            settings: conn.settings,
+            secret_ref: conn.secret_ref,
+        };
+        database.create_connector(connector_details.clone()).await?;
+        Ok(connector_details)
+    }
+
+    async fn get_available_datasets(
+        &self,
+        schema: Option<&str>,
+    ) -> Result<Vec<connector::AvailableDataset>> {
+        // The schema filter selects a folder below the root
+        let prefix = schema.map(|folder| format!("{}/", folder.trim_matches('/')));
+        let sources: Vec<Source> = self
+            .sources()
+            .await?
+            .into_iter()
+            .filter(|source| {
+                prefix
+                    .as_ref()
+                    .is_none_or(|prefix| source.path.starts_with(prefix))
+            })
+            .collect();
+
+        let mut datasets = Vec::with_capacity(sources.len());
+        for source in sources {
+            // Only Parquet files record their row count, read when no fetch is needed
+            let paths: Option<Vec<PathBuf>> = source
+                .files
+                .iter()
+                .map(|file| self.storage.cached(&file.object))
+                .collect();
+            let estimated_rows = match (source.format, paths) {
+                (Format::Parquet, Some(paths)) => Some(
+                    tokio::task::spawn_blocking(move || {
+                        paths
+                            .iter()
+                            .map(|path| parquet_rows(path))
+                            .sum::<Result<u64>>()
+                    })
+                    .await??,
+                ),
+                _ => None,
+            };
+            datasets.push(connector::AvailableDataset {
+                path: source.path,
+                kind: source.kind,
+                estimated_rows,
+                comment: None,
+            });
+        }
+        Ok(datasets)
+    }
+
+    async fn schemas(&self) -> Result<Vec<connector::SchemaAccess>> {
+        // Top-level folders act as schemas. Files directly below the root have an empty one.
+        // Listed files are fetched on demand, so each one counts as readable.
+        let mut schemas: Vec<connector::SchemaAccess> = Vec::new();
+        for source in self.sources().await? {
+            let name = source.path.split_once('/').map_or("", |(folder, _)| folder);
+            match schemas.iter_mut().find(|schema| schema.name == name) {
+                Some(schema) => {
+                    schema.datasets += 1;
+                    schema.readable += 1;
+                }
+                None => schemas.push(connector::SchemaAccess {
+                    name: name.to_string(),
+                    datasets: 1,
+                    readable: 1,
+                }),
+            }
+        }
+        Ok(schemas)
+    }
+
+    async fn get_data_info(&self, path: &str) -> Result<connector::DataInfo> {
+        let source = self.source(path).await?;
+        let sample = self.read(&source, INFER_ROWS).await?;
+
+        Ok(connector::DataInfo {
+            path: source.path,
+            schema: infer_schema(&sample),
+        })
+    }
+
+    fn dialect(&self) -> &dyn Dialect {
+        &SqliteDialect
+    }
+
+    async fn query(
+        &self,
+        sql: &str,
+        params: &[Value],
+        limit: u64,
+    ) -> Result<connector::QueryResult> {
+        // Load every dataset the query refers to by its quoted path
+        for source in self.sources().await? {
+            if sql.contains(&SqliteDialect.quote_identifier(&source.path)) {
+                self.load(source).await?;
+            }
+        }
+
+        sqliteconnector::query_read_only(&self.pool, sql, params, limit, self.statement_timeout)
+            .await
+    }
+
+    async fn preview(&self, path: &str, rows: u64, sample: bool) -> Result<connector::QueryResult> {
+        let source = self.source(path).await?;
+        let path = source.path.clone();
+        self.load(source).await?;
+        sqliteconnector::preview_table(&self.pool, &path, rows, sample, self.statement_timeout)
+            .await
+    }
+
+    async fn profile(&self, path: &str, schema: &Schema) -> Result<Profile> {
+        let source = self.source(path).await?;
+        let table = SqliteDialect.quote_path(&source.path);
+        self.load(source).await?;
+        sqliteconnector::profile_read_only(&self.pool, &table, schema, self.statement_timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[tokio::test]
+    async fn skips_symbolic_links() {
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.csv"), "a\n1\n").unwrap();
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("data.csv"), "a\n1\n").unwrap();
+        symlink(
+            outside.path().join("secret.csv"),
+            root.path().join("link.csv"),
+        )
+        .unwrap();
+        symlink(outside.path(), root.path().join("outside")).unwrap();
+        symlink(root.path(), root.path().join("loop")).unwrap();
+
+        let files = LocalFiles {
+            root: root.path().to_path_buf(),
+        };
+        let keys: Vec<String> = files
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|object| object.key)
+            .collect();
+        assert_eq!(keys, vec![String::from("data.csv")]);
+
+        let link = Object {
+            key: String::from("link.csv"),
+            version: String::new(),
+            size: 0,
+        };
+        assert!(files.fetch(&link).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_files_over_the_limit() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("small.csv"), "a\n1\n").unwrap();
+        std::fs::write(root.path().join("large.csv"), "a\n1\n2\n3\n4\n5\n").unwrap();
+        let files = LocalFiles {
+            root: root.path().to_path_buf(),
+        };
+        let connector = FileConnector::connect(files, Duration::from_secs(5), 8)
+            .await
+            .unwrap();
+
+        let small = connector.source("small.csv").await.unwrap();
+        assert_eq!(connector.read(&small, 10).await.unwrap().rows.len(), 1);
+        let large = connector.source("large.csv").await.unwrap();
+        let error = connector.read(&large, 10).await.unwrap_err();
+        assert!(error.to_string().contains("larger than"));
+    }
+}
@@ -1,19 +1,44 @@
+pub mod apikey;
 pub mod auth;
 pub mod common;
 pub mod connector;
 pub mod dataset;
+pub mod encryption;
+pub mod fileconnector;
+pub mod mysqlconnector;
+pub mod oidc;
 pub mod org;
 pub mod postgresconnector;
+pub mod profile;
+pub mod query;
+pub mod registry;
+pub mod role;
+pub mod s3connector;
+pub mod schema;
+pub mod secrets;
 pub mod session;
+pub mod sqliteconnector;
 pub mod team;
+pub mod tunnel;
 pub mod user;
 
+pub use apikey::{ApiKey, API_KEY_PREFIX};
 pub use auth::*;
 pub use common::*;
 pub use connector::*;
 pub use dataset::*;
+pub use encryption::*;
+pub use fileconnector::*;
+pub use mysqlconnector::*;
+pub use oidc::{Identity, Oidc};
 pub use org::*;
 pub use postgresconnector::*;
+pub use registry::*;
+pub use role::{Permission, Role};
+pub use s3connector::*;
+pub use secrets::*;
 pub use session::*;
+pub use sqliteconnector::*;
 pub use team::*;
+pub use tunnel::*;
 pub use user::*;
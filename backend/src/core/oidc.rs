@@ -0,0 +1,476 @@
+use crate::core::team::{self, Team};
+use crate::core::user::{self, User};
+use crate::core::Role;
+use crate::data::Database;
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use openidconnect::core::{
+    CoreAuthenticationFlow, CoreClient, CoreIdToken, CoreIdTokenClaims, CoreProviderMetadata,
+};
+use openidconnect::reqwest;
+use openidconnect::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet, EndpointNotSet,
+    EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
+    TokenResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::time::Duration;
+use tracing::info;
+use url::Url;
+
+type Client = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+/// Links a subject at an identity provider to a user.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Identity {
+    pub issuer: String,
+    pub subject: String,
+    pub user_id: String,
+}
+
+/// Kept by the browser between starting a login and the provider's callback.
+#[derive(Debug, Clone)]
+pub struct Pending {
+    pub state: String,
+    pub nonce: String,
+    pub pkce_verifier: String,
+}
+
+impl Pending {
+    pub fn encode(&self) -> String {
+        format!("{}.{}.{}", self.state, self.nonce, self.pkce_verifier)
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let mut parts = value.split('.');
+        let pending = Pending {
+            state: parts.next()?.to_string(),
+            nonce: parts.next()?.to_string(),
+            pkce_verifier: parts.next()?.to_string(),
+        };
+        parts.next().is_none().then_some(pending)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Callback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Single sign-on with an OpenID Connect provider, using the authorization
+/// code flow with PKCE.
+#[derive(Debug, Clone)]
+pub struct Oidc {
+    issuer: IssuerUrl,
+    client_id: ClientId,
+    client_secret: Option<ClientSecret>,
+    redirect_url: RedirectUrl,
+    scopes: Vec<String>,
+    groups_claim: String,
+    // (group, team name) and (group, user type), in order of precedence
+    group_teams: Vec<(String, String)>,
+    group_roles: Vec<(String, Role)>,
+    default_role: Role,
+    pub post_login_url: String,
+    http: reqwest::Client,
+}
+
+// Reads `group=value` pairs separated by commas
+fn mappings(var: &str) -> Result<Vec<(String, String)>> {
+    let Ok(value) = env::var(var) else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|mapping| !mapping.is_empty())
+        .map(|mapping| match mapping.split_once('=') {
+            Some((group, value)) if !group.trim().is_empty() && !value.trim().is_empty() => {
+                Ok((group.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(anyhow!("{var}: expected group=value, got '{mapping}'")),
+        })
+        .collect()
+}
+
+// Reads a claim the library does not model from a verified ID token
+fn claim_values(id_token: &CoreIdToken, claim: &str) -> Vec<String> {
+    let jwt = id_token.to_string();
+    let claims = jwt
+        .split('.')
+        .nth(1)
+        .and_then(|payload| URL_SAFE_NO_PAD.decode(payload).ok())
+        .and_then(|payload| serde_json::from_slice::<serde_json::Value>(&payload).ok());
+    match claims.as_ref().and_then(|claims| claims.get(claim)) {
+        Some(serde_json::Value::Array(values)) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(str::to_string))
+            .collect(),
+        Some(serde_json::Value::String(value)) => vec![value.clone()],
+        _ => Vec::new(),
+    }
+}
+
+impl Oidc {
+    /// Reads the provider from `OIDC_ISSUER_URL`, `OIDC_CLIENT_ID`,
+    /// `OIDC_CLIENT_SECRET` and `OIDC_REDIRECT_URL`. Single sign-on is disabled
+    /// when `OIDC_ISSUER_URL` is not set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(issuer) = env::var("OIDC_ISSUER_URL") else {
+            return Ok(None);
+        };
+        let client_id =
+            env::var("OIDC_CLIENT_ID").map_err(|_| anyhow!("OIDC_CLIENT_ID must be set"))?;
+        let redirect_url =
+            env::var("OIDC_REDIRECT_URL").map_err(|_| anyhow!("OIDC_REDIRECT_URL must be set"))?;
+        let scopes = env::var("OIDC_SCOPES").unwrap_or_else(|_| String::from("email profile"));
+        let group_roles = mappings("OIDC_GROUP_ROLES")?
+            .into_iter()
+            .map(|(group, role)| Ok((group, role.parse()?)))
+            .collect::<Result<_>>()?;
+        let default_role = match env::var("OIDC_DEFAULT_ROLE") {
+            Ok(role) => role.parse()?,
+            Err(_) => Role::Viewer,
+        };
+
+        Ok(Some(Oidc {
+            issuer: IssuerUrl::new(issuer)?,
+            client_id: ClientId::new(client_id),
+            client_secret: env::var("OIDC_CLIENT_SECRET").ok().map(ClientSecret::new),
+            redirect_url: RedirectUrl::new(redirect_url)?,
+            scopes: scopes.split_whitespace().map(str::to_string).collect(),
+            groups_claim: env::var("OIDC_GROUPS_CLAIM").unwrap_or_else(|_| String::from("groups")),
+            group_teams: mappings("OIDC_GROUP_TEAMS")?,
+            group_roles,
+            default_role,
+            post_login_url: env::var("OIDC_POST_LOGIN_URL").unwrap_or_else(|_| String::from("/")),
+            http: reqwest::ClientBuilder::new()
+                // Following redirects would allow requests to arbitrary hosts
+                .redirect(reqwest::redirect::Policy::none())
+                .timeout(Duration::from_secs(10))
+                .build()?,
+        }))
+    }
+
+    // Discovered on each login so provider key rotation is picked up
+    async fn client(&self) -> Result<Client> {
+        let metadata = CoreProviderMetadata::discover_async(self.issuer.clone(), &self.http)
+            .await
+            .map_err(|e| anyhow!("discovery failed: {e}"))?;
+        Ok(CoreClient::from_provider_metadata(
+            metadata,
+            self.client_id.clone(),
+            self.client_secret.clone(),
+        )
+        .set_redirect_uri(self.redirect_url.clone()))
+    }
+
+    /// Starts a login. Returns the provider URL to send the browser to, and
+    /// the state it must keep until the callback.
+    pub async fn begin(&self) -> Result<(Url, Pending)> {
+        let client = self.client().await?;
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let (url, state, nonce) = client
+            .authorize_url(
+                CoreAuthenticationFlow::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scopes(self.scopes.iter().cloned().map(Scope::new))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+        let pending = Pending {
+            state: state.secret().clone(),
+            nonce: nonce.secret().clone(),
+            pkce_verifier: pkce_verifier.secret().clone(),
+        };
+        Ok((url, pending))
+    }
+
+    /// Completes a login from the provider's callback, returning the user it
+    /// signs in as.
+    pub async fn complete<T: Database>(
+        &self,
+        database: T,
+        code: &str,
+        state: &str,
+        pending: Pending,
+    ) -> Result<User> {
+        if state != pending.state {
+            return Err(anyhow!("state does not match the login"));
+        }
+        let client = self.client().await?;
+        let token_response = client
+            .exchange_code(AuthorizationCode::new(code.to_string()))?
+            .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+            .request_async(&self.http)
+            .await
+            .map_err(|e| anyhow!("code exchange failed: {e}"))?;
+        let id_token = token_response
+            .id_token()
+            .ok_or_else(|| anyhow!("no ID token returned"))?;
+        let claims = id_token.claims(&client.id_token_verifier(), &Nonce::new(pending.nonce))?;
+        let groups = claim_values(id_token, &self.groups_claim);
+
+        let user = self.find_or_provision(database.clone(), claims).await?;
+        if !user.is_active {
+            return Err(anyhow!("user {} is not active", user.id));
+        }
+        self.sync(database, user, &groups).await
+    }
+
+    // Finds the user linked to the subject. Otherwise links the user with the
+    // same verified email, or creates one.
+    async fn find_or_provision<T: Database>(
+        &self,
+        database: T,
+        claims: &CoreIdTokenClaims,
+    ) -> Result<User> {
+        let issuer = claims.issuer().as_str();
+        let subject = claims.subject().as_str();
+        if let Ok(identity) = database.get_identity(issuer, subject).await {
+            return User::from_id(database, &identity.user_id).await;
+        }
+
+        let email = claims
+            .email()
+            .ok_or_else(|| anyhow!("no email claim for subject {subject}"))?;
+        if claims.email_verified() != Some(true) {
+            return Err(anyhow!("email {} is not verified", email.as_str()));
+        }
+        let user = if let Ok(user) = User::from_email(database.clone(), email).await {
+            info!("linking {subject} at {issuer} to user {}", user.id);
+            user
+        } else {
+            let user = User::create_without_password(
+                database.clone(),
+                user::Create {
+                    email: email.to_string(),
+                    first_name: claims
+                        .given_name()
+                        .and_then(|claim| claim.get(None))
+                        .map_or_else(String::new, |name| name.to_string()),
+                    last_name: claims
+                        .family_name()
+                        .and_then(|claim| claim.get(None))
+                        .map_or_else(String::new, |name| name.to_string()),
+                    r#type: self.default_role,
+                    password: String::new(),
+                },
+            )
+            .await?;
+            info!("provisioned user {} for {subject} at {issuer}", user.id);
+            user
+        };
+
+        database
+            .create_identity(&Identity {
+                issuer: issuer.to_string(),
+                subject: subject.to_string(),
+                user_id: user.id.clone(),
+            })
+            .await?;
+        Ok(user)
+    }
+
+    // Applies the group mappings. Each is only applied when configured, so the
+    // provider or the platform can own roles and teams independently.
+    async fn sync<T: Database>(
+        &self,
+        database: T,
+        mut user: User,
+        groups: &[String],
+    ) -> Result<User> {
+        if !self.group_roles.is_empty() {
+            let role = self
+                .group_roles
+                .iter()
+                .find(|(group, _)| groups.contains(group))
+                .map_or(self.default_role, |(_, role)| *role);
+            if user.r#type != role {
+                info!("user {} role {} -> {role}", user.id, user.r#type);
+                user.r#type = role;
+                database.create_user(&user).await?;
+            }
+        }
+
+        if !self.group_teams.is_empty() {
+            let mut teams = database.get_teams().await?;
+            let mut team_ids: Vec<String> = Vec::new();
+            for (group, name) in &self.group_teams {
+                if !groups.contains(group) {
+                    continue;
+                }
+                let team_id = match teams.iter().find(|team| team.name == *name) {
+                    Some(team) => team.id.clone(),
+                    None => {
+                        let team =
+                            Team::create(database.clone(), &team::Create { name: name.clone() })
+                                .await?;
+                        let team_id = team.id.clone();
+                        teams.push(team);
+                        team_id
+                    }
+                };
+                if !team_ids.contains(&team_id) {
+                    team_ids.push(team_id);
+                }
+            }
+            database.set_team_ids_for_user(&user.id, &team_ids).await?;
+        }
+        Ok(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Memory, UserStore};
+
+    // The mock-idp service in docker-compose.yml, which accepts any client
+    fn oidc() -> Oidc {
+        Oidc {
+            issuer: IssuerUrl::new(String::from("http://localhost:8080/default")).unwrap(),
+            client_id: ClientId::new(String::from("analytics-platform")),
+            client_secret: Some(ClientSecret::new(String::from("secret"))),
+            redirect_url: RedirectUrl::new(String::from("http://localhost:3001/oidc/callback"))
+                .unwrap(),
+            scopes: vec![String::from("email"), String::from("profile")],
+            groups_claim: String::from("groups"),
+            group_teams: vec![(String::from("analysts"), String::from("Analysts"))],
+            group_roles: vec![(String::from("analysts"), Role::Analyst)],
+            default_role: Role::Viewer,
+            post_login_url: String::from("/"),
+            http: reqwest::ClientBuilder::new()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap(),
+        }
+    }
+
+    // Submits the mock's login form, returning the code and state it redirects with
+    async fn sign_in(oidc: &Oidc, url: Url, claims: serde_json::Value) -> (String, String) {
+        let response = oidc
+            .http
+            .post(url)
+            .form(&[("username", "alice"), ("claims", &claims.to_string())])
+            .send()
+            .await
+            .unwrap();
+        let location = Url::parse(response.headers()["location"].to_str().unwrap()).unwrap();
+        let param = |name: &str| {
+            location
+                .query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string())
+                .unwrap()
+        };
+        (param("code"), param("state"))
+    }
+
+    #[test]
+    fn round_trips_pending_logins() {
+        let pending = Pending {
+            state: String::from("state"),
+            nonce: String::from("nonce"),
+            pkce_verifier: String::from("verifier"),
+        };
+        let decoded = Pending::decode(&pending.encode()).unwrap();
+        assert_eq!(decoded.state, "state");
+        assert_eq!(decoded.nonce, "nonce");
+        assert_eq!(decoded.pkce_verifier, "verifier");
+        assert!(Pending::decode("state.nonce").is_none());
+        assert!(Pending::decode("state.nonce.verifier.extra").is_none());
+    }
+
+    #[test]
+    fn reads_group_mappings() {
+        env::set_var(
+            "OIDC_TEST_GROUP_TEAMS",
+            "analysts = Analysts, ,admins=Platform Admins",
+        );
+        assert_eq!(
+            mappings("OIDC_TEST_GROUP_TEAMS").unwrap(),
+            vec![
+                (String::from("analysts"), String::from("Analysts")),
+                (String::from("admins"), String::from("Platform Admins")),
+            ]
+        );
+        env::set_var("OIDC_TEST_GROUP_ROLES", "analysts");
+        assert!(mappings("OIDC_TEST_GROUP_ROLES").is_err());
+        assert!(mappings("OIDC_TEST_UNSET").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the mock-idp service from docker-compose.yml"]
+    async fn provisions_users_from_the_provider() {
+        let oidc = oidc();
+        let database = Memory::default();
+        let claims = serde_json::json!({
+            "email": "alice@example.com",
+            "email_verified": true,
+            "groups": ["analysts"]
+        });
+
+        let (url, pending) = oidc.begin().await.unwrap();
+        let (code, state) = sign_in(&oidc, url, claims.clone()).await;
+        let user = oidc
+            .complete(database.clone(), &code, &state, pending)
+            .await
+            .unwrap();
+        assert_eq!(user.email, "alice@example.com");
+        assert_eq!(user.r#type, Role::Analyst);
+        let teams = database.get_teams().await.unwrap();
+        assert_eq!(teams.len(), 1);
+        assert_eq!(teams[0].name, "Analysts");
+
+        // Signing in again finds the linked user
+        let (url, pending) = oidc.begin().await.unwrap();
+        let (code, state) = sign_in(&oidc, url, claims).await;
+        let again = oidc
+            .complete(database, &code, &state, pending)
+            .await
+            .unwrap();
+        assert_eq!(again.id, user.id);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the mock-idp service from docker-compose.yml"]
+    async fn rejects_a_mismatched_state() {
+        let oidc = oidc();
+        let (url, pending) = oidc.begin().await.unwrap();
+        let claims = serde_json::json!({ "email": "bob@example.com", "email_verified": true });
+        let (code, _) = sign_in(&oidc, url, claims).await;
+        let error = oidc
+            .complete(Memory::default(), &code, "forged", pending)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("state"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the mock-idp service from docker-compose.yml"]
+    async fn rejects_unverified_emails() {
+        let oidc = oidc();
+        let (url, pending) = oidc.begin().await.unwrap();
+        let claims = serde_json::json!({ "email": "eve@example.com", "email_verified": false });
+        let (code, state) = sign_in(&oidc, url, claims).await;
+        let error = oidc
+            .complete(Memory::default(), &code, &state, pending)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not verified"));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::core::now;
+use serde::{Deserialize, Serialize};
+
+/// Number of most frequent values kept per column.
+pub const TOP_VALUES: u64 = 5;
+/// Number of equal-width buckets in numeric histograms.
+pub const HISTOGRAM_BUCKETS: u64 = 10;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ValueCount {
+    pub value: Option<String>,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Bucket {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ColumnProfile {
+    pub name: String,
+    pub data_type: String,
+    pub null_fraction: f64,
+    pub distinct_count: u64,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub top_values: Vec<ValueCount>,
+    // Only computed for numeric columns
+    pub histogram: Option<Vec<Bucket>>,
+}
+
+/// Column statistics of a dataset, computed through its connector.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Profile {
+    pub row_count: u64,
+    pub columns: Vec<ColumnProfile>,
+    // Seconds since the Unix epoch
+    pub profiled_at: u64,
+}
+
+impl Profile {
+    pub fn is_stale(&self, max_age_secs: u64) -> bool {
+        now().saturating_sub(self.profiled_at) > max_age_secs
+    }
+}
@@ -0,0 +1,479 @@
+use crate::core::{connector, schema::Schema};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Structured query over a registered dataset. Compiled by each connector
+/// into its own SQL dialect so clients never send raw SQL.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Query {
+    #[serde(default)]
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub aggregates: Vec<Aggregate>,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    #[serde(default)]
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Function {
+    Count,
+    CountDistinct,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Aggregate {
+    pub function: Function,
+    // None counts all rows
+    pub column: Option<String>,
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Like,
+    In,
+    IsNull,
+    IsNotNull,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Filter {
+    pub column: String,
+    pub op: Operator,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OrderBy {
+    // Selected column or aggregate alias
+    pub column: String,
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+/// SQL text with positional parameters, ready to run on a connector.
+#[derive(Debug, Clone)]
+pub struct Compiled {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+/// Connector-specific SQL syntax.
+pub trait Dialect: Send + Sync {
+    fn quote_identifier(&self, identifier: &str) -> String;
+
+    // Placeholder for the parameter at `index`, starting at 1
+    fn placeholder(&self, index: usize) -> String;
+
+    // Comparable expression for a string parameter against a column of `data_type`
+    fn string_param(&self, placeholder: &str, _data_type: &str) -> String {
+        placeholder.to_string()
+    }
+
+    // Column expression usable with LIKE
+    fn text_column(&self, column: &str) -> String {
+        column.to_string()
+    }
+
+    fn quote_path(&self, path: &str) -> String {
+        path.split('.')
+            .map(|part| self.quote_identifier(part))
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+}
+
+impl Function {
+    fn sql(self, argument: &str) -> String {
+        match self {
+            Function::Count => format!("COUNT({argument})"),
+            Function::CountDistinct => format!("COUNT(DISTINCT {argument})"),
+            Function::Sum => format!("SUM({argument})"),
+            Function::Avg => format!("AVG({argument})"),
+            Function::Min => format!("MIN({argument})"),
+            Function::Max => format!("MAX({argument})"),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Function::Count => "count",
+            Function::CountDistinct => "count_distinct",
+            Function::Sum => "sum",
+            Function::Avg => "avg",
+            Function::Min => "min",
+            Function::Max => "max",
+        }
+    }
+}
+
+impl Aggregate {
+    pub fn alias(&self) -> String {
+        self.alias.clone().unwrap_or_else(|| match &self.column {
+            Some(column) => format!("{}_{column}", self.function.name()),
+            None => self.function.name().to_string(),
+        })
+    }
+}
+
+impl Query {
+    pub fn row_limit(&self) -> u64 {
+        self.limit
+            .unwrap_or(connector::DEFAULT_ROW_LIMIT)
+            .min(connector::MAX_ROW_LIMIT)
+    }
+
+    fn validate(&self, schema: &Schema) -> Result<()> {
+        let check_column = |column: &String| {
+            if schema.contains(column) {
+                Ok(())
+            } else {
+                Err(anyhow!("Unknown column: {column}"))
+            }
+        };
+
+        if self.columns.is_empty() && self.aggregates.is_empty() {
+            return Err(anyhow!(
+                "Query must select at least one column or aggregate"
+            ));
+        }
+
+        self.columns.iter().try_for_each(check_column)?;
+        self.group_by.iter().try_for_each(check_column)?;
+        self.filters
+            .iter()
+            .try_for_each(|filter| check_column(&filter.column))?;
+
+        for aggregate in &self.aggregates {
+            match &aggregate.column {
+                Some(column) => check_column(column)?,
+                None if aggregate.function != Function::Count => {
+                    return Err(anyhow!(
+                        "Aggregate '{}' requires a column",
+                        aggregate.function.name()
+                    ));
+                }
+                None => {}
+            }
+        }
+
+        if !self.aggregates.is_empty() || !self.group_by.is_empty() {
+            if let Some(column) = self
+                .columns
+                .iter()
+                .find(|column| !self.group_by.contains(column))
+            {
+                return Err(anyhow!(
+                    "Column '{column}' must be grouped when aggregating"
+                ));
+            }
+        }
+
+        let aliases: Vec<String> = self.aggregates.iter().map(Aggregate::alias).collect();
+        for order in &self.order_by {
+            if !self.columns.contains(&order.column) && !aliases.contains(&order.column) {
+                return Err(anyhow!("Order column '{}' is not selected", order.column));
+            }
+        }
+
+        for filter in &self.filters {
+            match (filter.op, &filter.value) {
+                (Operator::IsNull | Operator::IsNotNull, _) => {}
+                (Operator::In, Some(Value::Array(values))) if !values.is_empty() => {}
+                (Operator::In, _) => {
+                    return Err(anyhow!(
+                        "Filter 'in' on '{}' requires a list",
+                        filter.column
+                    ));
+                }
+                (Operator::Like, Some(Value::String(_))) => {}
+                (Operator::Like, _) => {
+                    return Err(anyhow!(
+                        "Filter 'like' on '{}' requires a string",
+                        filter.column
+                    ));
+                }
+                (_, Some(Value::Array(_) | Value::Object(_) | Value::Null) | None) => {
+                    return Err(anyhow!("Filter on '{}' requires a value", filter.column));
+                }
+                (_, Some(_)) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validates the query against a dataset schema and compiles it to SQL.
+    pub fn compile(&self, path: &str, schema: &Schema, dialect: &dyn Dialect) -> Result<Compiled> {
+        self.validate(schema)?;
+
+        let mut params = Vec::new();
+        let mut bind = |value: &Value, data_type: &str| {
+            params.push(value.clone());
+            let placeholder = dialect.placeholder(params.len());
+            match value {
+                Value::String(_) => dialect.string_param(&placeholder, data_type),
+                _ => placeholder,
+            }
+        };
+
+        let mut select: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| dialect.quote_identifier(column))
+            .collect();
+        for aggregate in &self.aggregates {
+            let argument = aggregate.column.as_ref().map_or_else(
+                || String::from("*"),
+                |column| dialect.quote_identifier(column),
+            );
+            select.push(format!(
+                "{} AS {}",
+                aggregate.function.sql(&argument),
+                dialect.quote_identifier(&aggregate.alias())
+            ));
+        }
+
+        let mut sql = format!(
+            "SELECT {} FROM {}",
+            select.join(", "),
+            dialect.quote_path(path)
+        );
+
+        let mut conditions = Vec::new();
+        for filter in &self.filters {
+            let column = dialect.quote_identifier(&filter.column);
+            let data_type = schema
+                .column(&filter.column)
+                .map_or("", |column| column.data_type.as_str());
+            let condition = match (filter.op, &filter.value) {
+                (Operator::IsNull, _) => format!("{column} IS NULL"),
+                (Operator::IsNotNull, _) => format!("{column} IS NOT NULL"),
+                (Operator::In, Some(Value::Array(values))) => {
+                    let placeholders: Vec<String> =
+                        values.iter().map(|value| bind(value, data_type)).collect();
+                    format!("{column} IN ({})", placeholders.join(", "))
+                }
+                (Operator::Like, Some(value)) => {
+                    // Parameter is bound as text regardless of column type
+                    format!(
+                        "{} LIKE {}",
+                        dialect.text_column(&column),
+                        bind(value, "text")
+                    )
+                }
+                (op, Some(value)) => {
+                    let symbol = match op {
+                        Operator::Eq => "=",
+                        Operator::Ne => "<>",
+                        Operator::Lt => "<",
+                        Operator::Lte => "<=",
+                        Operator::Gt => ">",
+                        _ => ">=",
+                    };
+                    format!("{column} {symbol} {}", bind(value, data_type))
+                }
+                (_, None) => unreachable!("validated filters have values"),
+            };
+            conditions.push(condition);
+        }
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+
+        if !self.group_by.is_empty() {
+            let group_by: Vec<String> = self
+                .group_by
+                .iter()
+                .map(|column| dialect.quote_identifier(column))
+                .collect();
+            sql.push_str(&format!(" GROUP BY {}", group_by.join(", ")));
+        }
+
+        if !self.order_by.is_empty() {
+            let order_by: Vec<String> = self
+                .order_by
+                .iter()
+                .map(|order| {
+                    let direction = match order.direction {
+                        Direction::Asc => "ASC",
+                        Direction::Desc => "DESC",
+                    };
+                    format!("{} {direction}", dialect.quote_identifier(&order.column))
+                })
+                .collect();
+            sql.push_str(&format!(" ORDER BY {}", order_by.join(", ")));
+        }
+
+        Ok(Compiled { sql, params })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::schema::Column;
+    use crate::core::{MySqlDialect, PostgresDialect, SqliteDialect};
+    use serde_json::json;
+
+    fn schema() -> Schema {
+        Schema {
+            columns: vec![
+                Column::new("id", "integer"),
+                Column::new("region", "text"),
+                Column::new("amount", "numeric"),
+                Column::new("created_at", "timestamp without time zone"),
+                Column::new("odd\"name`", "text"),
+            ],
+        }
+    }
+
+    fn query(value: Value) -> Query {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn error(value: Value) -> String {
+        query(value)
+            .compile("public.sales", &schema(), &PostgresDialect)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn compiles_for_each_dialect() {
+        let query = query(json!({
+            "columns": ["region"],
+            "aggregates": [{ "function": "sum", "column": "amount" }, { "function": "count" }],
+            "filters": [
+                { "column": "created_at", "op": "gte", "value": "2024-01-01" },
+                { "column": "id", "op": "in", "value": [1, 2] },
+                { "column": "region", "op": "like", "value": "E%" },
+                { "column": "amount", "op": "is_not_null" }
+            ],
+            "group_by": ["region"],
+            "order_by": [{ "column": "sum_amount", "direction": "desc" }]
+        }));
+
+        let postgres = query
+            .compile("public.sales", &schema(), &PostgresDialect)
+            .unwrap();
+        assert_eq!(
+            postgres.sql,
+            "SELECT \"region\", SUM(\"amount\") AS \"sum_amount\", COUNT(*) AS \"count\" \
+             FROM \"public\".\"sales\" \
+             WHERE \"created_at\" >= CAST($1 AS timestamp without time zone) \
+             AND \"id\" IN ($2, $3) AND CAST(\"region\" AS text) LIKE CAST($4 AS text) \
+             AND \"amount\" IS NOT NULL \
+             GROUP BY \"region\" ORDER BY \"sum_amount\" DESC"
+        );
+        assert_eq!(
+            postgres.params,
+            vec![json!("2024-01-01"), json!(1), json!(2), json!("E%")]
+        );
+
+        let mysql = query
+            .compile("shop.sales", &schema(), &MySqlDialect)
+            .unwrap();
+        assert_eq!(
+            mysql.sql,
+            "SELECT `region`, SUM(`amount`) AS `sum_amount`, COUNT(*) AS `count` \
+             FROM `shop`.`sales` \
+             WHERE `created_at` >= ? AND `id` IN (?, ?) AND CAST(`region` AS CHAR) LIKE ? \
+             AND `amount` IS NOT NULL \
+             GROUP BY `region` ORDER BY `sum_amount` DESC"
+        );
+
+        let sqlite = query
+            .compile("sales.csv", &schema(), &SqliteDialect)
+            .unwrap();
+        assert_eq!(
+            sqlite.sql,
+            "SELECT \"region\", SUM(\"amount\") AS \"sum_amount\", COUNT(*) AS \"count\" \
+             FROM \"sales.csv\" \
+             WHERE \"created_at\" >= ?1 AND \"id\" IN (?2, ?3) AND CAST(\"region\" AS TEXT) LIKE ?4 \
+             AND \"amount\" IS NOT NULL \
+             GROUP BY \"region\" ORDER BY \"sum_amount\" DESC"
+        );
+    }
+
+    #[test]
+    fn quotes_identifiers() {
+        let query = query(json!({ "columns": ["odd\"name`"] }));
+        assert_eq!(
+            query.compile("t", &schema(), &PostgresDialect).unwrap().sql,
+            "SELECT \"odd\"\"name`\" FROM \"t\""
+        );
+        assert_eq!(
+            query.compile("t", &schema(), &MySqlDialect).unwrap().sql,
+            "SELECT `odd\"name``` FROM `t`"
+        );
+        assert_eq!(
+            query.compile("t", &schema(), &SqliteDialect).unwrap().sql,
+            "SELECT \"odd\"\"name`\" FROM \"t\""
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        assert!(error(json!({})).contains("at least one column"));
+        assert!(error(json!({ "columns": ["missing"] })).contains("Unknown column"));
+        assert!(
+            error(json!({ "filters": [{ "column": "id; DROP", "op": "eq", "value": 1 }], "columns": ["id"] }))
+                .contains("Unknown column")
+        );
+        assert!(
+            error(json!({ "aggregates": [{ "function": "sum" }] })).contains("requires a column")
+        );
+        assert!(error(json!({
+            "columns": ["region"],
+            "aggregates": [{ "function": "count" }]
+        }))
+        .contains("must be grouped"));
+        assert!(error(json!({
+            "columns": ["id"],
+            "order_by": [{ "column": "region" }]
+        }))
+        .contains("is not selected"));
+        assert!(error(json!({
+            "columns": ["id"],
+            "filters": [{ "column": "id", "op": "in", "value": [] }]
+        }))
+        .contains("requires a list"));
+        assert!(error(json!({
+            "columns": ["id"],
+            "filters": [{ "column": "region", "op": "like", "value": 1 }]
+        }))
+        .contains("requires a string"));
+        assert!(error(json!({
+            "columns": ["id"],
+            "filters": [{ "column": "id", "op": "eq" }]
+        }))
+        .contains("requires a value"));
+    }
+}
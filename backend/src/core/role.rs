@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Permission {
+    #[serde(rename = "datasets:read")]
+    DatasetsRead,
+    #[serde(rename = "datasets:write")]
+    DatasetsWrite,
+    #[serde(rename = "query:run")]
+    QueryRun,
+    #[serde(rename = "connectors:admin")]
+    ConnectorsAdmin,
+    #[serde(rename = "teams:admin")]
+    TeamsAdmin,
+    #[serde(rename = "users:admin")]
+    UsersAdmin,
+    #[serde(rename = "service_accounts:admin")]
+    ServiceAccountsAdmin,
+    // Orgs and platform-wide operations such as re-encrypting secrets
+    #[serde(rename = "platform:admin")]
+    PlatformAdmin,
+}
+
+/// What a user is allowed to do. Stored as the user's type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Analyst,
+    DataSteward,
+    OrgAdmin,
+    PlatformAdmin,
+    // Owns API keys, which limit it further with their scopes
+    #[serde(rename = "service")]
+    ServiceAccount,
+}
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Role::Viewer => &[DatasetsRead],
+            Role::Analyst => &[DatasetsRead, QueryRun],
+            Role::DataSteward => &[DatasetsRead, QueryRun, DatasetsWrite, ConnectorsAdmin],
+            Role::OrgAdmin => &[
+                DatasetsRead,
+                QueryRun,
+                DatasetsWrite,
+                ConnectorsAdmin,
+                TeamsAdmin,
+                UsersAdmin,
+                ServiceAccountsAdmin,
+            ],
+            Role::PlatformAdmin => &[
+                DatasetsRead,
+                QueryRun,
+                DatasetsWrite,
+                ConnectorsAdmin,
+                TeamsAdmin,
+                UsersAdmin,
+                ServiceAccountsAdmin,
+                PlatformAdmin,
+            ],
+            Role::ServiceAccount => &[DatasetsRead, QueryRun, DatasetsWrite, ConnectorsAdmin],
+        }
+    }
+
+    pub fn permits(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+
+    /// Whether this role can do everything `other` can, so a user with this
+    /// role may grant it without escalating.
+    pub fn includes(&self, other: Role) -> bool {
+        other
+            .permissions()
+            .iter()
+            .all(|permission| self.permits(*permission))
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Analyst => write!(f, "analyst"),
+            Role::DataSteward => write!(f, "data_steward"),
+            Role::OrgAdmin => write!(f, "org_admin"),
+            Role::PlatformAdmin => write!(f, "platform_admin"),
+            Role::ServiceAccount => write!(f, "service"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "analyst" => Ok(Role::Analyst),
+            "data_steward" => Ok(Role::DataSteward),
+            "org_admin" => Ok(Role::OrgAdmin),
+            "platform_admin" => Ok(Role::PlatformAdmin),
+            "service" => Ok(Role::ServiceAccount),
+            _ => Err(anyhow!("unknown role: {s}")),
+        }
+    }
+}
+
+// Reads stored user types, including those from before roles
+impl From<String> for Role {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "superadmin" => Role::PlatformAdmin,
+            "user" => Role::Analyst,
+            _ => s.parse().unwrap_or_else(|_| {
+                warn!("unknown user type {s}, treating it as viewer");
+                Role::Viewer
+            }),
+        }
+    }
+}
+
+/// Body of `PUT /users/:user_id/role`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Assign {
+    pub role: Role,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROLES: [Role; 6] = [
+        Role::Viewer,
+        Role::Analyst,
+        Role::DataSteward,
+        Role::OrgAdmin,
+        Role::PlatformAdmin,
+        Role::ServiceAccount,
+    ];
+
+    #[test]
+    fn includes_lower_roles() {
+        for role in ROLES {
+            assert!(role.includes(role));
+            assert!(role.includes(Role::Viewer));
+            assert!(Role::PlatformAdmin.includes(role));
+        }
+        assert!(Role::OrgAdmin.includes(Role::DataSteward));
+        assert!(!Role::OrgAdmin.includes(Role::PlatformAdmin));
+        assert!(!Role::Analyst.includes(Role::DataSteward));
+        assert!(!Role::DataSteward.includes(Role::OrgAdmin));
+        assert!(!Role::Viewer.includes(Role::Analyst));
+        assert!(!Role::ServiceAccount.permits(Permission::UsersAdmin));
+    }
+
+    #[test]
+    fn round_trips_names() {
+        for role in ROLES {
+            assert_eq!(role.to_string().parse::<Role>().unwrap(), role);
+            assert_eq!(Role::from(role.to_string()), role);
+            assert_eq!(
+                serde_json::to_value(role).unwrap(),
+                serde_json::Value::String(role.to_string())
+            );
+        }
+        assert!("superadmin".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn maps_legacy_user_types() {
+        assert_eq!(Role::from(String::from("superadmin")), Role::PlatformAdmin);
+        assert_eq!(Role::from(String::from("user")), Role::Analyst);
+        assert_eq!(Role::from(String::from("unknown")), Role::Viewer);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::core::connector::{ConnectionError, ErrorKind};
+use crate::core::{FileConnector, Object, Storage};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_config::meta::region::RegionProviderChain;
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::config::Credentials;
+use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
+use aws_sdk_s3::Client;
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+pub type S3Connector = FileConnector<S3Bucket>;
+
+/// Bucket in S3 or an S3-compatible store such as MinIO. Objects are
+/// downloaded to a local cache when they are first read.
+#[derive(Debug, Clone)]
+pub struct S3Bucket {
+    pub client: Client,
+    pub bucket: String,
+    // Keys are listed below this prefix, without a trailing '/'
+    pub prefix: String,
+    cache: Arc<TempDir>,
+    // key -> version of the cached copy
+    fetched: Arc<Mutex<HashMap<String, String>>>,
+}
+
+fn invalid(message: &str) -> anyhow::Error {
+    ConnectionError::new(ErrorKind::InvalidConfiguration, message).into()
+}
+
+// Listed keys become cache paths, so they may not leave the cache directory
+fn is_safe_key(key: &str) -> bool {
+    !key.is_empty()
+        && Path::new(key)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+impl S3Bucket {
+    /// Connects from `s3://[access_key:secret_key@]bucket[/prefix][?endpoint=url&region=name]`.
+    /// Keys must be percent-encoded. Without keys the server's own AWS credentials are
+    /// used, but only if the operator allows it with `S3_AMBIENT_CREDENTIALS=true`.
+    pub async fn connect(connection_string: &str) -> Result<Self> {
+        let url = Url::parse(connection_string)?;
+        if url.scheme() != "s3" {
+            return Err(invalid("Expected an s3:// URL"));
+        }
+        let bucket = url
+            .host_str()
+            .ok_or_else(|| invalid("Missing bucket name"))?
+            .to_string();
+        let prefix = url.path().trim_matches('/').to_string();
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+        let region_provider = RegionProviderChain::first_try(
+            params
+                .get("region")
+                .cloned()
+                .map(aws_sdk_s3::config::Region::new),
+        )
+        .or_default_provider()
+        .or_else("eu-west-2");
+        let mut loader = aws_config::defaults(BehaviorVersion::latest()).region(region_provider);
+        if !url.username().is_empty() {
+            let secret_key = url
+                .password()
+                .ok_or_else(|| invalid("Missing secret key"))?;
+            loader = loader.credentials_provider(Credentials::new(
+                percent_decode_str(url.username()).decode_utf8()?,
+                percent_decode_str(secret_key).decode_utf8()?,
+                None,
+                None,
+                "connection-string",
+            ));
+        } else if !env::var("S3_AMBIENT_CREDENTIALS").is_ok_and(|value| value == "true") {
+            // The server's credentials could read buckets the user can not
+            return Err(invalid("Missing access key and secret key"));
+        }
+        let defaults = loader.load().await;
+
+        // Set endpoint url for S3-compatible stores, which mostly need path-style URLs
+        let config = if let Some(endpoint) = params.get("endpoint") {
+            aws_sdk_s3::config::Builder::from(&defaults)
+                .endpoint_url(endpoint)
+                .force_path_style(true)
+                .build()
+        } else {
+            aws_sdk_s3::config::Builder::from(&defaults).build()
+        };
+        let client = Client::from_conf(config);
+
+        // Check the bucket exists and is readable
+        client
+            .head_bucket()
+            .bucket(&bucket)
+            .send()
+            .await
+            .map_err(|e| match &e {
+                SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => ConnectionError::new(
+                    ErrorKind::Unreachable,
+                    format!("Bucket '{bucket}': {}", DisplayErrorContext(e)),
+                ),
+                // HEAD responses have no body, so only the status tells what failed
+                SdkError::ServiceError(error) if error.raw().status().as_u16() == 403 => {
+                    ConnectionError::new(
+                        ErrorKind::PermissionDenied,
+                        format!("Access to bucket '{bucket}' denied"),
+                    )
+                }
+                SdkError::ServiceError(error) if error.raw().status().as_u16() == 404 => {
+                    ConnectionError::new(
+                        ErrorKind::NotFound,
+                        format!("Bucket '{bucket}' does not exist"),
+                    )
+                }
+                _ => ConnectionError::new(
+                    ErrorKind::Unknown,
+                    format!("Bucket '{bucket}': {}", DisplayErrorContext(e)),
+                ),
+            })?;
+
+        Ok(S3Bucket {
+            client,
+            bucket,
+            prefix,
+            cache: Arc::new(TempDir::new()?),
+            fetched: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    fn full_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{key}", self.prefix)
+        }
+    }
+
+    fn cached_version(&self, key: &str) -> Option<String> {
+        self.fetched
+            .lock()
+            .expect("fetched lock poisoned")
+            .get(key)
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl Storage for S3Bucket {
+    async fn list(&self) -> Result<Vec<Object>> {
+        let prefix = self.full_key("");
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let output = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(&prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(|e| anyhow!("{}", DisplayErrorContext(e)))?;
+
+            for object in output.contents() {
+                let Some(key) = object
+                    .key()
+                    .and_then(|key| key.strip_prefix(&prefix))
+                    .filter(|key| is_safe_key(key) && !key.ends_with('/'))
+                else {
+                    continue;
+                };
+                objects.push(Object {
+                    key: key.to_string(),
+                    version: format!(
+                        "{}-{}",
+                        object.e_tag().unwrap_or_default(),
+                        object.size().unwrap_or_default()
+                    ),
+                    size: object.size().unwrap_or_default().max(0) as u64,
+                });
+            }
+
+            continuation_token = output.next_continuation_token().map(String::from);
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+        Ok(objects)
+    }
+
+    async fn fetch(&self, object: &Object) -> Result<PathBuf> {
+        if let Some(path) = self.cached(object) {
+            return Ok(path);
+        }
+        if !is_safe_key(&object.key) {
+            return Err(anyhow!("Invalid object key: '{}'", object.key));
+        }
+
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.full_key(&object.key))
+            .send()
+            .await
+            .map_err(|e| anyhow!("{}", DisplayErrorContext(e)))?;
+
+        let path = self.cache.path().join(&object.key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Streamed to disk, stopping if the object grew past its listed size
+        let mut body = output.body;
+        let mut file = tokio::fs::File::create(&path).await?;
+        let mut written = 0;
+        while let Some(chunk) = body.try_next().await? {
+            written += chunk.len() as u64;
+            if written > object.size {
+                drop(file);
+                tokio::fs::remove_file(&path).await?;
+                return Err(anyhow!("'{}' changed while it was read", object.key));
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        self.fetched
+            .lock()
+            .expect("fetched lock poisoned")
+            .insert(object.key.clone(), object.version.clone());
+        Ok(path)
+    }
+
+    fn cached(&self, object: &Object) -> Option<PathBuf> {
+        (self.cached_version(&object.key).as_ref() == Some(&object.version))
+            .then(|| self.cache.path().join(&object.key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::connector::{Connector, Details, Settings, Type};
+    use aws_sdk_s3::config::Region;
+    use aws_sdk_s3::primitives::ByteStream;
+
+    // The minio service in docker-compose.yml
+    const ENDPOINT: &str = "http://localhost:9000";
+    const BUCKET: &str = "connector-test";
+
+    async fn upload(objects: &[(&str, Vec<u8>)]) {
+        let config = aws_sdk_s3::config::Builder::new()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .endpoint_url(ENDPOINT)
+            .force_path_style(true)
+            .credentials_provider(Credentials::new("user", "password", None, None, "test"))
+            .build();
+        let client = Client::from_conf(config);
+        // Fails when the bucket is left from an earlier run
+        let _ = client.create_bucket().bucket(BUCKET).send().await;
+        for (key, body) in objects {
+            client
+                .put_object()
+                .bucket(BUCKET)
+                .key(*key)
+                .body(ByteStream::from(body.clone()))
+                .send()
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn connect(connection_string: &str, settings: Settings) -> Result<Connector> {
+        Connector::connect(&Details {
+            id: String::from("s3"),
+            name: String::from("s3"),
+            r#type: Type::S3,
+            connection_string: connection_string.to_string(),
+            settings,
+            secret_ref: false,
+        })
+        .await
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the minio service from docker-compose.yml"]
+    async fn reads_objects_below_the_prefix() {
+        let mut large = b"id\n".to_vec();
+        while large.len() < 2 * 1024 * 1024 {
+            large.extend_from_slice(b"1234567890\n");
+        }
+        upload(&[
+            ("sales/2024.csv", b"id,region\n1,EU\n2,US\n".to_vec()),
+            ("sales/large.csv", large),
+            ("other/ignored.csv", b"id\n1\n".to_vec()),
+        ])
+        .await;
+
+        let connection_string =
+            format!("s3://user:password@{BUCKET}/sales?endpoint={ENDPOINT}&region=us-east-1");
+        let connector = connect(
+            &connection_string,
+            Settings {
+                max_file_size_mb: Some(1),
+                ..Settings::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut paths: Vec<String> = connector
+            .get_available_datasets(None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|dataset| dataset.path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["2024.csv", "large.csv"]);
+
+        let preview = connector.preview("2024.csv", 10, false).await.unwrap();
+        assert_eq!(preview.rows.len(), 2);
+        let error = connector.preview("large.csv", 10, false).await.unwrap_err();
+        assert!(error.to_string().contains("larger than"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the minio service from docker-compose.yml"]
+    async fn reports_wrong_keys() {
+        upload(&[]).await;
+        let connection_string =
+            format!("s3://user:wrong@{BUCKET}?endpoint={ENDPOINT}&region=us-east-1");
+        let error = connect(&connection_string, Settings::default())
+            .await
+            .unwrap_err();
+        let error = error.downcast_ref::<ConnectionError>().unwrap();
+        assert_eq!(error.kind, ErrorKind::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn requires_keys_unless_ambient_credentials_are_allowed() {
+        let error = S3Bucket::connect("s3://bucket?endpoint=http://localhost:1")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Missing access key"));
+    }
+}
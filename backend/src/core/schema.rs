@@ -0,0 +1,232 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ForeignKey {
+    // Referenced table as "schema.table"
+    pub table: String,
+    pub column: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Column {
+    pub name: String,
+    pub data_type: String,
+    #[serde(default = "default_nullable")]
+    pub nullable: bool,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub primary_key: bool,
+    #[serde(default)]
+    pub foreign_key: Option<ForeignKey>,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum SchemaChange {
+    ColumnAdded {
+        column: Column,
+    },
+    ColumnRemoved {
+        name: String,
+    },
+    TypeChanged {
+        name: String,
+        from: String,
+        to: String,
+    },
+}
+
+/// Ordered column definitions of a table or other dataset.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Schema {
+    pub columns: Vec<Column>,
+}
+
+fn default_nullable() -> bool {
+    true
+}
+
+impl Column {
+    pub fn new(name: &str, data_type: &str) -> Self {
+        Column {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable: true,
+            default: None,
+            primary_key: false,
+            foreign_key: None,
+            comment: None,
+        }
+    }
+}
+
+impl Schema {
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.column(name).is_some()
+    }
+
+    /// Column additions, removals and type changes from `self` to `other`.
+    pub fn diff(&self, other: &Schema) -> Vec<SchemaChange> {
+        let mut changes = Vec::new();
+
+        for column in &self.columns {
+            match other.column(&column.name) {
+                None => changes.push(SchemaChange::ColumnRemoved {
+                    name: column.name.clone(),
+                }),
+                Some(new) if new.data_type != column.data_type => {
+                    changes.push(SchemaChange::TypeChanged {
+                        name: column.name.clone(),
+                        from: column.data_type.clone(),
+                        to: new.data_type.clone(),
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        for column in &other.columns {
+            if !self.contains(&column.name) {
+                changes.push(SchemaChange::ColumnAdded {
+                    column: column.clone(),
+                });
+            }
+        }
+
+        changes
+    }
+}
+
+// Schemas stored before column details were kept are a map of column name to
+// type. They are read in name order since the original order is unknown.
+impl<'de> Deserialize<'de> for Schema {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Columns { columns: Vec<Column> },
+            Legacy(HashMap<String, String>),
+        }
+
+        match Stored::deserialize(deserializer)? {
+            Stored::Columns { columns } => Ok(Schema { columns }),
+            Stored::Legacy(types) => {
+                let mut columns: Vec<Column> = types
+                    .iter()
+                    .map(|(name, data_type)| Column::new(name, data_type))
+                    .collect();
+                columns.sort_by(|a, b| a.name.cmp(&b.name));
+                Ok(Schema { columns })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_column_lists() {
+        let schema: Schema = serde_json::from_value(json!({
+            "columns": [
+                { "name": "id", "data_type": "integer", "nullable": false, "primary_key": true },
+                {
+                    "name": "team_id",
+                    "data_type": "integer",
+                    "foreign_key": { "table": "public.teams", "column": "id" }
+                }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(schema.columns.len(), 2);
+        assert!(schema.columns[0].primary_key && !schema.columns[0].nullable);
+        assert!(schema.columns[1].nullable);
+        assert_eq!(
+            schema.columns[1].foreign_key,
+            Some(ForeignKey {
+                table: String::from("public.teams"),
+                column: String::from("id"),
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<Schema>(serde_json::to_value(&schema).unwrap()).unwrap(),
+            schema
+        );
+    }
+
+    #[test]
+    fn reads_legacy_maps_in_name_order() {
+        let schema: Schema =
+            serde_json::from_value(json!({ "name": "text", "id": "integer", "age": "integer" }))
+                .unwrap();
+        assert_eq!(
+            schema,
+            Schema {
+                columns: vec![
+                    Column::new("age", "integer"),
+                    Column::new("id", "integer"),
+                    Column::new("name", "text"),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn diffs_columns() {
+        let old = Schema {
+            columns: vec![
+                Column::new("id", "integer"),
+                Column::new("name", "text"),
+                Column::new("amount", "integer"),
+            ],
+        };
+        let new = Schema {
+            columns: vec![
+                Column::new("id", "integer"),
+                Column::new("amount", "numeric"),
+                Column::new("created_at", "timestamp"),
+            ],
+        };
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                SchemaChange::ColumnRemoved {
+                    name: String::from("name"),
+                },
+                SchemaChange::TypeChanged {
+                    name: String::from("amount"),
+                    from: String::from("integer"),
+                    to: String::from("numeric"),
+                },
+                SchemaChange::ColumnAdded {
+                    column: Column::new("created_at", "timestamp"),
+                },
+            ]
+        );
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn ignores_order_and_details_when_diffing() {
+        let old = Schema {
+            columns: vec![Column::new("a", "text"), Column::new("b", "text")],
+        };
+        let mut b = Column::new("b", "text");
+        b.nullable = false;
+        b.comment = Some(String::from("now documented"));
+        let new = Schema {
+            columns: vec![b, Column::new("a", "text")],
+        };
+        assert!(old.diff(&new).is_empty());
+    }
+}
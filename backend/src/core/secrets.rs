@@ -0,0 +1,315 @@
+use crate::core::connector::{ConnectionError, ErrorKind};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::env;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Connection strings starting with this name a secret holding the real one.
+pub const SECRET_SCHEME: &str = "secret://";
+
+/// Looks up secrets by name, e.g. "warehouse/readonly", and for providers
+/// that store several values per secret, by field.
+#[async_trait]
+pub trait SecretsProvider: Debug + Send + Sync {
+    async fn get(&self, name: &str, field: Option<&str>) -> Result<String>;
+}
+
+fn no_fields(field: Option<&str>) -> Result<()> {
+    match field {
+        Some(field) => Err(anyhow!("'#{field}': only vault secrets have fields")),
+        None => Ok(()),
+    }
+}
+
+/// Environment variables named after the secret, e.g. `SECRET_WAREHOUSE_READONLY`.
+#[derive(Debug, Clone)]
+pub struct EnvSecrets;
+
+#[async_trait]
+impl SecretsProvider for EnvSecrets {
+    async fn get(&self, name: &str, field: Option<&str>) -> Result<String> {
+        no_fields(field)?;
+        let var: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        env::var(format!("SECRET_{var}")).map_err(|_| anyhow!("SECRET_{var} is not set"))
+    }
+}
+
+/// One file per secret below a directory, as mounted by Docker or Kubernetes.
+#[derive(Debug, Clone)]
+pub struct FileSecrets {
+    pub root: PathBuf,
+}
+
+#[async_trait]
+impl SecretsProvider for FileSecrets {
+    async fn get(&self, name: &str, field: Option<&str>) -> Result<String> {
+        no_fields(field)?;
+        let path = self.root.join(name);
+        let secret = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+/// Key/value (version 2) secrets engine of HashiCorp Vault. The value is the
+/// secret's `connection_string` field unless the reference names another, as in
+/// `secret://bastion#private_key`.
+#[derive(Debug, Clone)]
+pub struct VaultSecrets {
+    pub client: reqwest::Client,
+    pub address: String,
+    pub token: String,
+    // Path the secrets engine is mounted at
+    pub mount: String,
+}
+
+const DEFAULT_VAULT_FIELD: &str = "connection_string";
+
+#[async_trait]
+impl SecretsProvider for VaultSecrets {
+    async fn get(&self, name: &str, field: Option<&str>) -> Result<String> {
+        let field = field.unwrap_or(DEFAULT_VAULT_FIELD);
+        let url = format!(
+            "{}/v1/{}/data/{name}",
+            self.address.trim_end_matches('/'),
+            self.mount
+        );
+        let response = self
+            .client
+            .get(&url)
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await?;
+
+        match response.status().as_u16() {
+            200 => {}
+            403 => return Err(anyhow!("Vault denied access to '{name}'")),
+            404 => return Err(anyhow!("'{name}' does not exist in Vault")),
+            status => return Err(anyhow!("Vault returned status {status} for '{name}'")),
+        }
+        let body: Value = response.json().await?;
+        body["data"]["data"][field]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow!("Vault secret '{name}' has no {field} field"))
+    }
+}
+
+fn is_valid_component(component: &str) -> bool {
+    !component.is_empty()
+        && component != "."
+        && component != ".."
+        && component
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+// Splits "path#field" into the secret's path and field
+fn split_field(name: &str) -> (&str, Option<&str>) {
+    match name.split_once('#') {
+        Some((path, field)) => (path, Some(field)),
+        None => (name, None),
+    }
+}
+
+// Names are '/' separated components of letters, digits, '.', '_' and '-',
+// optionally followed by '#' and a field made of the same characters
+fn is_valid_name(name: &str) -> bool {
+    let (path, field) = split_field(name);
+    path.split('/').all(is_valid_component) && field.is_none_or(is_valid_component)
+}
+
+/// Resolves `secret://` references in connection strings.
+#[derive(Debug, Clone)]
+pub struct Secrets {
+    provider: Arc<dyn SecretsProvider>,
+}
+
+// Environment variables, as when SECRETS_PROVIDER is not set
+impl Default for Secrets {
+    fn default() -> Self {
+        Secrets::new(Arc::new(EnvSecrets))
+    }
+}
+
+impl Secrets {
+    pub fn new(provider: Arc<dyn SecretsProvider>) -> Self {
+        Secrets { provider }
+    }
+
+    /// Uses the provider named by `SECRETS_PROVIDER`: `env` (default), `file` or `vault`.
+    pub fn from_env() -> Result<Self> {
+        let provider = env::var("SECRETS_PROVIDER").unwrap_or_else(|_| String::from("env"));
+        let provider: Arc<dyn SecretsProvider> = match provider.as_str() {
+            "env" => Arc::new(EnvSecrets),
+            "file" => Arc::new(FileSecrets {
+                root: env::var("SECRETS_DIR")
+                    .map_err(|_| anyhow!("SECRETS_DIR is required for file secrets"))?
+                    .into(),
+            }),
+            "vault" => Arc::new(VaultSecrets {
+                client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(5))
+                    .build()?,
+                address: env::var("VAULT_ADDR")
+                    .map_err(|_| anyhow!("VAULT_ADDR is required for vault secrets"))?,
+                token: env::var("VAULT_TOKEN")
+                    .map_err(|_| anyhow!("VAULT_TOKEN is required for vault secrets"))?,
+                mount: env::var("VAULT_MOUNT").unwrap_or_else(|_| String::from("secret")),
+            }),
+            _ => return Err(anyhow!("Invalid secrets provider: {provider}")),
+        };
+        Ok(Secrets::new(provider))
+    }
+
+    /// Returns the connection string a reference names, or the string itself
+    /// if it is not a reference.
+    pub async fn resolve(&self, connection_string: &str) -> Result<String> {
+        let Some(name) = connection_string.strip_prefix(SECRET_SCHEME) else {
+            return Ok(connection_string.to_string());
+        };
+        if !is_valid_name(name) {
+            return Err(ConnectionError::new(
+                ErrorKind::InvalidConfiguration,
+                format!("Invalid secret name: '{name}'"),
+            )
+            .into());
+        }
+        let (path, field) = split_field(name);
+        self.provider.get(path, field).await.map_err(|e| {
+            ConnectionError::new(ErrorKind::Secret, format!("Secret '{name}': {e}")).into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_names_outside_the_store() {
+        assert!(is_valid_name("warehouse/readonly"));
+        assert!(is_valid_name("warehouse.prod_1-a"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("/warehouse"));
+        assert!(!is_valid_name("warehouse//readonly"));
+        assert!(!is_valid_name("warehouse/../admin"));
+        assert!(!is_valid_name("warehouse readonly"));
+        assert!(is_valid_name("bastion#private_key"));
+        assert!(!is_valid_name("bastion#"));
+        assert!(!is_valid_name("bastion#a#b"));
+        assert!(!is_valid_name("#private_key"));
+    }
+
+    #[tokio::test]
+    async fn resolves_only_references() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("warehouse")).unwrap();
+        std::fs::write(dir.path().join("warehouse/readonly"), "postgres://db\n").unwrap();
+        let secrets = Secrets::new(Arc::new(FileSecrets {
+            root: dir.path().to_path_buf(),
+        }));
+
+        assert_eq!(
+            secrets.resolve("postgres://plain").await.unwrap(),
+            "postgres://plain"
+        );
+        assert_eq!(
+            secrets
+                .resolve("secret://warehouse/readonly")
+                .await
+                .unwrap(),
+            "postgres://db"
+        );
+        let error = secrets
+            .resolve("secret://warehouse/missing")
+            .await
+            .unwrap_err();
+        assert_eq!(ConnectionError::from(&error).kind, ErrorKind::Secret);
+        let error = secrets.resolve("secret://../etc/passwd").await.unwrap_err();
+        assert_eq!(
+            ConnectionError::from(&error).kind,
+            ErrorKind::InvalidConfiguration
+        );
+    }
+
+    #[tokio::test]
+    async fn only_vault_secrets_have_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("bastion"), "key\n").unwrap();
+        let secrets = Secrets::new(Arc::new(FileSecrets {
+            root: dir.path().to_path_buf(),
+        }));
+        assert_eq!(secrets.resolve("secret://bastion").await.unwrap(), "key");
+        assert!(secrets.resolve("secret://bastion#key").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_environment_variables() {
+        env::set_var("SECRET_TEST_ENV_READONLY", "postgres://env");
+        let secrets = Secrets::default();
+        assert_eq!(
+            secrets.resolve("secret://test-env/readonly").await.unwrap(),
+            "postgres://env"
+        );
+        assert!(secrets.resolve("secret://test-env/unset").await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the vault service from docker-compose.yml"]
+    async fn reads_vault_fields() {
+        // The dev server's root token, set in docker-compose.yml
+        let vault = VaultSecrets {
+            client: reqwest::Client::new(),
+            address: String::from("http://localhost:8200"),
+            token: String::from("root"),
+            mount: String::from("secret"),
+        };
+        vault
+            .client
+            .post(format!("{}/v1/secret/data/test/warehouse", vault.address))
+            .header("X-Vault-Token", &vault.token)
+            .json(&serde_json::json!({
+                "data": { "connection_string": "postgres://db", "private_key": "key" }
+            }))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        let secrets = Secrets::new(Arc::new(vault));
+        assert_eq!(
+            secrets.resolve("secret://test/warehouse").await.unwrap(),
+            "postgres://db"
+        );
+        assert_eq!(
+            secrets
+                .resolve("secret://test/warehouse#private_key")
+                .await
+                .unwrap(),
+            "key"
+        );
+        let missing_field = secrets
+            .resolve("secret://test/warehouse#password")
+            .await
+            .unwrap_err();
+        assert!(missing_field.to_string().contains("has no password field"));
+        let missing = secrets.resolve("secret://test/missing").await.unwrap_err();
+        assert!(missing.to_string().contains("does not exist"));
+    }
+}
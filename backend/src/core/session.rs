@@ -1,43 +1,239 @@
-use crate::core::create_id;
 use crate::core::User;
+use crate::core::{create_id, now};
 use crate::data::Database;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::Serialize;
+use std::env;
+
+// Sessions are written back at most this often when used, in seconds
+const RENEW_INTERVAL: u64 = 60;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Session {
     pub id: String,
     pub user_id: Option<String>,
+    // Seconds since the Unix epoch
+    pub created_at: u64,
+    pub last_seen_at: u64,
+    pub expires_at: u64,
+}
+
+/// How long sessions last, in seconds. A session expires once it is unused
+/// for its idle timeout, or once it reaches its maximum age however often
+/// it is used.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionPolicy {
+    pub idle_timeout: u64,
+    pub max_age: u64,
+    pub anonymous_idle_timeout: u64,
+    pub anonymous_max_age: u64,
+    // Whether session cookies are only sent over HTTPS
+    pub secure_cookies: bool,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        SessionPolicy {
+            idle_timeout: 8 * 60 * 60,
+            max_age: 7 * 24 * 60 * 60,
+            anonymous_idle_timeout: 30 * 60,
+            anonymous_max_age: 24 * 60 * 60,
+            secure_cookies: true,
+        }
+    }
+}
+
+fn env_secs(var: &str, default: u64) -> Result<u64> {
+    match env::var(var) {
+        Ok(value) => match value.parse() {
+            Ok(0) | Err(_) => Err(anyhow!("{var} must be a positive number of seconds")),
+            Ok(secs) => Ok(secs),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+impl SessionPolicy {
+    /// Reads the timeouts from `SESSION_IDLE_TIMEOUT_SECS`, `SESSION_MAX_AGE_SECS`,
+    /// `ANONYMOUS_SESSION_IDLE_TIMEOUT_SECS` and `ANONYMOUS_SESSION_MAX_AGE_SECS`.
+    /// `SESSION_COOKIE_SECURE=false` allows session cookies over plain HTTP.
+    pub fn from_env() -> Result<Self> {
+        let defaults = SessionPolicy::default();
+        Ok(SessionPolicy {
+            idle_timeout: env_secs("SESSION_IDLE_TIMEOUT_SECS", defaults.idle_timeout)?,
+            max_age: env_secs("SESSION_MAX_AGE_SECS", defaults.max_age)?,
+            anonymous_idle_timeout: env_secs(
+                "ANONYMOUS_SESSION_IDLE_TIMEOUT_SECS",
+                defaults.anonymous_idle_timeout,
+            )?,
+            anonymous_max_age: env_secs(
+                "ANONYMOUS_SESSION_MAX_AGE_SECS",
+                defaults.anonymous_max_age,
+            )?,
+            secure_cookies: env::var("SESSION_COOKIE_SECURE")
+                .map_or(true, |value| value != "false"),
+        })
+    }
+
+    // Idle timeout and maximum age of a session
+    fn limits(&self, session: &Session) -> (u64, u64) {
+        if session.user_id.is_some() {
+            (self.idle_timeout, self.max_age)
+        } else {
+            (self.anonymous_idle_timeout, self.anonymous_max_age)
+        }
+    }
+
+    /// When the session reaches its maximum age, however often it is used.
+    pub fn max_expires_at(&self, session: &Session) -> u64 {
+        let (_, max_age) = self.limits(session);
+        session.created_at + max_age
+    }
+
+    fn expires_at(&self, session: &Session) -> u64 {
+        let (idle_timeout, _) = self.limits(session);
+        (session.last_seen_at + idle_timeout).min(self.max_expires_at(session))
+    }
 }
 
 impl Session {
-    // TODO: dead code
     pub async fn from_id<T: Database>(database: T, id: &str) -> Result<Self> {
         database.get_session_by_id(id).await
     }
 
-    pub async fn create<T: Database>(database: T, user: Option<&User>) -> Result<Self> {
-        let session_id = create_id(30).await;
-
-        match user {
-            Some(u) => database.create_session(Some(u), &session_id).await?,
-            None => database.create_session(None, &session_id).await?,
+    pub async fn create<T: Database>(
+        database: T,
+        user: Option<&User>,
+        policy: &SessionPolicy,
+    ) -> Result<Self> {
+        let now = now();
+        let mut session = Session {
+            id: create_id(30).await,
+            user_id: user.map(|u| u.id.clone()),
+            created_at: now,
+            last_seen_at: now,
+            expires_at: now,
         };
+        session.expires_at = policy.expires_at(&session);
+        database.create_session(&session).await?;
+        Ok(session)
+    }
 
-        match user {
-            Some(u) => Ok(Session {
-                id: session_id,
-                user_id: Some(u.clone().id),
-            }),
-            None => Ok(Session {
-                id: session_id,
-                user_id: None,
-            }),
+    pub fn is_expired(&self) -> bool {
+        now() >= self.expires_at
+    }
+
+    /// Extends a session that is in use. Expired sessions are deleted instead.
+    pub async fn renew<T: Database>(&mut self, database: T, policy: &SessionPolicy) -> Result<()> {
+        if self.is_expired() {
+            database.delete_session(&self.id).await?;
+            return Err(anyhow!("session expired"));
+        }
+        let now = now();
+        let (idle_timeout, _) = policy.limits(self);
+        // Avoid a write per request, but renew well before the idle timeout
+        if now.saturating_sub(self.last_seen_at) < RENEW_INTERVAL.min(idle_timeout / 2) {
+            return Ok(());
         }
+        self.last_seen_at = now;
+        self.expires_at = policy.expires_at(self);
+        database.update_session(self).await
     }
 
     pub async fn delete<T: Database>(database: T, id: &str) -> Result<()> {
         database.delete_session(id).await?;
         Ok(())
     }
+
+    /// Deletes expired sessions. Returns how many were deleted.
+    pub async fn purge_expired<T: Database>(database: T) -> Result<u64> {
+        database.delete_expired_sessions(now()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Memory, SessionStore};
+
+    const POLICY: SessionPolicy = SessionPolicy {
+        idle_timeout: 600,
+        max_age: 3_600,
+        anonymous_idle_timeout: 60,
+        anonymous_max_age: 120,
+        secure_cookies: true,
+    };
+
+    // A session created `age` seconds ago and last used `idle` seconds ago
+    async fn session(database: &Memory, user_id: Option<&str>, age: u64, idle: u64) -> Session {
+        let now = now();
+        let mut session = Session {
+            id: create_id(30).await,
+            user_id: user_id.map(String::from),
+            created_at: now - age,
+            last_seen_at: now - idle,
+            expires_at: now,
+        };
+        session.expires_at = POLICY.expires_at(&session);
+        database.create_session(&session).await.unwrap();
+        session
+    }
+
+    #[tokio::test]
+    async fn expires_when_idle_or_too_old() {
+        let database = Memory::default();
+
+        let fresh = session(&database, Some("user"), 0, 0).await;
+        assert_eq!(fresh.expires_at, fresh.last_seen_at + POLICY.idle_timeout);
+        // Renewal never extends past the maximum age
+        let old = session(&database, Some("user"), 3_300, 0).await;
+        assert_eq!(old.expires_at, old.created_at + POLICY.max_age);
+        let anonymous = session(&database, None, 0, 0).await;
+        assert_eq!(
+            anonymous.expires_at,
+            anonymous.last_seen_at + POLICY.anonymous_idle_timeout
+        );
+
+        assert!(!session(&database, Some("user"), 700, 599)
+            .await
+            .is_expired());
+        assert!(session(&database, Some("user"), 700, 600)
+            .await
+            .is_expired());
+        assert!(session(&database, None, 120, 10).await.is_expired());
+    }
+
+    #[tokio::test]
+    async fn renews_sessions_in_use() {
+        let database = Memory::default();
+
+        // Recently renewed sessions are not written again
+        let mut recent = session(&database, Some("user"), 100, 10).await;
+        let expires_at = recent.expires_at;
+        recent.renew(database.clone(), &POLICY).await.unwrap();
+        assert_eq!(recent.expires_at, expires_at);
+
+        let mut idle = session(&database, Some("user"), 500, 300).await;
+        idle.renew(database.clone(), &POLICY).await.unwrap();
+        assert_eq!(idle.expires_at, idle.last_seen_at + POLICY.idle_timeout);
+        let stored = Session::from_id(database.clone(), &idle.id).await.unwrap();
+        assert_eq!(stored.expires_at, idle.expires_at);
+
+        let mut expired = session(&database, Some("user"), 1_000, 900).await;
+        assert!(expired.renew(database.clone(), &POLICY).await.is_err());
+        assert!(Session::from_id(database.clone(), &expired.id)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn purges_expired_sessions() {
+        let database = Memory::default();
+        let live = session(&database, Some("user"), 0, 0).await;
+        session(&database, Some("user"), 1_000, 900).await;
+        session(&database, None, 200, 0).await;
+
+        assert_eq!(Session::purge_expired(database.clone()).await.unwrap(), 2);
+        assert!(Session::from_id(database.clone(), &live.id).await.is_ok());
+    }
 }
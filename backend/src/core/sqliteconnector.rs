@@ -0,0 +1,690 @@
+use crate::core::common::create_id;
+use crate::core::connector;
+use crate::core::now;
+use crate::core::profile::{self, Bucket, ColumnProfile, Profile, ValueCount};
+use crate::core::query::Dialect;
+use crate::core::schema::{Column as SchemaColumn, ForeignKey, Schema};
+use crate::data::Database;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use libsqlite3_sys as ffi;
+use serde_json::{Number, Value};
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::{SqliteArguments, SqliteRow};
+use sqlx::{
+    Arguments, Column, Executor, Row, Sqlite, SqliteConnection, SqlitePool, TypeInfo, ValueRef,
+};
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::ptr;
+use std::time::{Duration, Instant};
+
+/// SQLite database file, opened read-only.
+#[derive(Debug, Clone)]
+pub struct SqliteConnector {
+    pub pool: SqlitePool,
+    pub statement_timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SqliteDialect;
+
+impl Dialect for SqliteDialect {
+    fn quote_identifier(&self, identifier: &str) -> String {
+        format!("\"{}\"", identifier.replace('"', "\"\""))
+    }
+
+    fn placeholder(&self, index: usize) -> String {
+        format!("?{index}")
+    }
+
+    fn text_column(&self, column: &str) -> String {
+        format!("CAST({column} AS TEXT)")
+    }
+
+    // Table names and file paths may contain dots, so each dataset is a single table
+    fn quote_path(&self, path: &str) -> String {
+        self.quote_identifier(path)
+    }
+}
+
+// Column affinity SQLite derives from a declared type
+fn affinity(data_type: &str) -> &'static str {
+    let data_type = data_type.to_uppercase();
+    if data_type.contains("INT") {
+        "integer"
+    } else if ["CHAR", "CLOB", "TEXT"]
+        .iter()
+        .any(|name| data_type.contains(name))
+    {
+        "text"
+    } else if data_type.is_empty() || data_type.contains("BLOB") {
+        "blob"
+    } else if ["REAL", "FLOA", "DOUB"]
+        .iter()
+        .any(|name| data_type.contains(name))
+    {
+        "real"
+    } else {
+        "numeric"
+    }
+}
+
+pub(crate) fn bind_value(arguments: &mut SqliteArguments<'_>, value: &Value) {
+    match value {
+        Value::String(s) => arguments.add(s.clone()),
+        Value::Bool(b) => arguments.add(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => arguments.add(i),
+            None => arguments.add(n.as_f64()),
+        },
+        Value::Null => arguments.add(None::<String>),
+        // Nested values are kept as JSON text
+        _ => arguments.add(value.to_string()),
+    }
+}
+
+fn bind_params(params: &[Value]) -> Result<SqliteArguments<'static>> {
+    let mut arguments = SqliteArguments::default();
+    for param in params {
+        match param {
+            Value::Array(_) | Value::Object(_) => {
+                return Err(anyhow!("Unsupported parameter: {param}"))
+            }
+            _ => bind_value(&mut arguments, param),
+        }
+    }
+    Ok(arguments)
+}
+
+fn row_values(row: &SqliteRow, columns: &[connector::ResultColumn]) -> Result<Vec<Value>> {
+    let mut values = Vec::with_capacity(columns.len());
+    for (index, column) in columns.iter().enumerate() {
+        let raw = row.try_get_raw(index)?;
+        let value = if raw.is_null() {
+            Value::Null
+        } else {
+            match raw.type_info().name() {
+                "INTEGER" if column.data_type == "boolean" => {
+                    Value::Bool(row.try_get::<i64, _>(index)? != 0)
+                }
+                "INTEGER" => Value::from(row.try_get::<i64, _>(index)?),
+                "REAL" => Number::from_f64(row.try_get(index)?).map_or(Value::Null, Value::Number),
+                "TEXT" => Value::String(row.try_get(index)?),
+                _ => Value::Null,
+            }
+        };
+        values.push(value);
+    }
+    Ok(values)
+}
+
+// Equal-width histogram between the column's min and max values
+async fn histogram(
+    conn: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+    min: f64,
+    max: f64,
+) -> Result<Vec<Bucket>> {
+    let buckets = i32::try_from(profile::HISTOGRAM_BUCKETS)?;
+    let width = (max - min) / f64::from(buckets);
+    if width <= 0.0 {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT count({column}) FROM {table}"))
+            .fetch_one(&mut *conn)
+            .await?;
+        return Ok(vec![Bucket {
+            lower: min,
+            upper: max,
+            count: count as u64,
+        }]);
+    }
+
+    // The max value falls past the last bucket, so it is folded into it
+    let counts: Vec<(i64, i64)> = sqlx::query_as(&format!(
+        "SELECT min(CAST(({column} - ?1) / ?2 AS INTEGER) + 1, ?3), count(*)
+         FROM {table} WHERE {column} IS NOT NULL GROUP BY 1"
+    ))
+    .bind(min)
+    .bind(width)
+    .bind(buckets)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok((1..=buckets)
+        .map(|bucket| {
+            let lower = min + width * f64::from(bucket - 1);
+            let count = counts
+                .iter()
+                .find(|(b, _)| *b == i64::from(bucket))
+                .map_or(0, |(_, count)| *count);
+            Bucket {
+                lower,
+                upper: lower + width,
+                count: count as u64,
+            }
+        })
+        .collect())
+}
+
+async fn profile_table(
+    conn: &mut SqliteConnection,
+    table: &str,
+    schema: &Schema,
+) -> Result<Profile> {
+    let row_count: i64 = sqlx::query_scalar(&format!("SELECT count(*) FROM {table}"))
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let mut columns = Vec::with_capacity(schema.columns.len());
+    for schema_column in &schema.columns {
+        let name = &schema_column.name;
+        let data_type = schema_column.data_type.as_str();
+        let column = SqliteDialect.quote_identifier(name);
+        // Booleans are stored as integers but have no useful range
+        let is_numeric =
+            data_type != "boolean" && matches!(affinity(data_type), "integer" | "real" | "numeric");
+
+        let (min, max) = if is_numeric || affinity(data_type) == "text" {
+            (
+                format!("CAST(min({column}) AS TEXT)"),
+                format!("CAST(max({column}) AS TEXT)"),
+            )
+        } else {
+            (String::from("NULL"), String::from("NULL"))
+        };
+        let (nulls, distinct, min, max): (i64, i64, Option<String>, Option<String>) =
+            sqlx::query_as(&format!(
+                "SELECT coalesce(sum({column} IS NULL), 0),
+                            count(DISTINCT {column}),
+                            {min}, {max}
+                     FROM {table}"
+            ))
+            .fetch_one(&mut *conn)
+            .await?;
+
+        let top_values: Vec<(Option<String>, i64)> = sqlx::query_as(&format!(
+            "SELECT CAST({column} AS TEXT), count(*) FROM {table}
+                 GROUP BY 1 ORDER BY 2 DESC, 1 LIMIT {}",
+            profile::TOP_VALUES
+        ))
+        .fetch_all(&mut *conn)
+        .await?;
+
+        // Columns are loosely typed, so a numeric column may still hold text
+        let range = min
+            .as_deref()
+            .zip(max.as_deref())
+            .and_then(|(min, max)| Some((min.parse().ok()?, max.parse().ok()?)));
+        let histogram = match (is_numeric, range) {
+            (true, Some((min, max))) => Some(histogram(conn, table, &column, min, max).await?),
+            _ => None,
+        };
+        columns.push(ColumnProfile {
+            name: name.clone(),
+            data_type: data_type.to_string(),
+            null_fraction: if row_count > 0 {
+                nulls as f64 / row_count as f64
+            } else {
+                0.0
+            },
+            distinct_count: distinct as u64,
+            min,
+            max,
+            top_values: top_values
+                .into_iter()
+                .map(|(value, count)| ValueCount {
+                    value,
+                    count: count as u64,
+                })
+                .collect(),
+            histogram,
+        });
+    }
+
+    Ok(Profile {
+        row_count: row_count as u64,
+        columns,
+        profiled_at: now(),
+    })
+}
+
+// Denies statements that could reach other files or turn off `query_only`
+extern "C" fn authorize(
+    _: *mut c_void,
+    action: c_int,
+    _: *const c_char,
+    _: *const c_char,
+    _: *const c_char,
+    _: *const c_char,
+) -> c_int {
+    match action {
+        ffi::SQLITE_ATTACH | ffi::SQLITE_DETACH | ffi::SQLITE_PRAGMA => ffi::SQLITE_DENY,
+        _ => ffi::SQLITE_OK,
+    }
+}
+
+/// Connection that can not write and interrupts statements after the timeout.
+/// It is closed instead of returning to the pool unless `release` restores it,
+/// e.g. when the request is dropped part way through.
+struct ReadOnly {
+    conn: Option<PoolConnection<Sqlite>>,
+}
+
+impl ReadOnly {
+    async fn acquire(pool: &SqlitePool, timeout: Duration) -> Result<Self> {
+        let mut read_only = ReadOnly {
+            conn: Some(pool.acquire().await?),
+        };
+        let conn = read_only.conn();
+        conn.execute("PRAGMA query_only = ON").await?;
+        let deadline = Instant::now() + timeout;
+        let mut handle = conn.lock_handle().await?;
+        handle.set_progress_handler(1000, move || Instant::now() < deadline);
+        // SAFETY: the handle is locked, and the callback is a plain function
+        unsafe {
+            ffi::sqlite3_set_authorizer(
+                handle.as_raw_handle().as_ptr(),
+                Some(authorize),
+                ptr::null_mut(),
+            );
+        }
+        drop(handle);
+        Ok(read_only)
+    }
+
+    fn conn(&mut self) -> &mut SqliteConnection {
+        self.conn.as_mut().expect("connection already released")
+    }
+
+    // sqlx runs every statement in a string, so anything after the first one
+    // would run outside the read-only wrapper
+    async fn check_single_statement(&mut self, sql: &str) -> Result<()> {
+        let sql = CString::new(sql)?;
+        let mut handle = self.conn().lock_handle().await?;
+        let db = handle.as_raw_handle().as_ptr();
+        let mut statement = ptr::null_mut();
+        let mut tail = ptr::null();
+        // SAFETY: the handle is locked, and the statement is finalized before
+        // `sql`, which `tail` points into, is dropped
+        let (code, rest) = unsafe {
+            let code = ffi::sqlite3_prepare_v2(db, sql.as_ptr(), -1, &mut statement, &mut tail);
+            ffi::sqlite3_finalize(statement);
+            let rest = if code == ffi::SQLITE_OK {
+                CStr::from_ptr(tail).to_string_lossy().into_owned()
+            } else {
+                CStr::from_ptr(ffi::sqlite3_errmsg(db))
+                    .to_string_lossy()
+                    .into_owned()
+            };
+            (code, rest)
+        };
+        if code != ffi::SQLITE_OK {
+            return Err(anyhow!(rest));
+        }
+        if !rest.trim().is_empty() {
+            return Err(anyhow!("Only a single SELECT statement can be run"));
+        }
+        Ok(())
+    }
+
+    // Makes the connection writable again before it returns to the pool
+    async fn release(mut self) -> Result<()> {
+        let conn = self.conn();
+        {
+            let mut handle = conn.lock_handle().await?;
+            handle.remove_progress_handler();
+            // SAFETY: the handle is locked
+            unsafe {
+                ffi::sqlite3_set_authorizer(handle.as_raw_handle().as_ptr(), None, ptr::null_mut());
+            }
+        }
+        conn.execute("PRAGMA query_only = OFF").await?;
+        // Restored, so the connection can go back to the pool
+        self.conn.take();
+        Ok(())
+    }
+}
+
+impl Drop for ReadOnly {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            drop(conn.detach());
+        }
+    }
+}
+
+/// Runs a query on a read-only connection, returning at most `limit` rows.
+/// Statements running longer than `timeout` are interrupted.
+pub(crate) async fn query_read_only(
+    pool: &SqlitePool,
+    sql: &str,
+    params: &[Value],
+    limit: u64,
+    timeout: Duration,
+) -> Result<connector::QueryResult> {
+    let sql = sql.trim().trim_end_matches(';');
+    // Fetch one extra row to detect truncation
+    let limited = format!("SELECT * FROM ({sql}) AS q LIMIT {}", limit + 1);
+    let arguments = bind_params(params)?;
+
+    let mut read_only = ReadOnly::acquire(pool, timeout).await?;
+    let result: Result<(Vec<connector::ResultColumn>, Vec<SqliteRow>)> = async {
+        read_only.check_single_statement(&limited).await?;
+        let conn = read_only.conn();
+        let columns = (&mut *conn)
+            .describe(&limited)
+            .await?
+            .columns()
+            .iter()
+            .map(|column| connector::ResultColumn {
+                name: column.name().to_string(),
+                data_type: column.type_info().name().to_lowercase(),
+            })
+            .collect();
+        let rows = sqlx::query_with(&limited, arguments)
+            .fetch_all(&mut *conn)
+            .await?;
+        Ok((columns, rows))
+    }
+    .await;
+    read_only.release().await?;
+    let (columns, rows) = result?;
+
+    let truncated = rows.len() as u64 > limit;
+    let rows = rows
+        .iter()
+        .take(limit as usize)
+        .map(|row| row_values(row, &columns))
+        .collect::<Result<Vec<Vec<Value>>>>()?;
+
+    Ok(connector::QueryResult {
+        columns,
+        rows,
+        truncated,
+    })
+}
+
+/// Profiles a table on a read-only connection.
+pub(crate) async fn profile_read_only(
+    pool: &SqlitePool,
+    table: &str,
+    schema: &Schema,
+    timeout: Duration,
+) -> Result<Profile> {
+    let mut read_only = ReadOnly::acquire(pool, timeout).await?;
+    let result = profile_table(read_only.conn(), table, schema).await;
+    read_only.release().await?;
+    result
+}
+
+/// First `rows` rows of a table, or a random sample of them.
+pub(crate) async fn preview_table(
+    pool: &SqlitePool,
+    path: &str,
+    rows: u64,
+    sample: bool,
+    timeout: Duration,
+) -> Result<connector::QueryResult> {
+    let table = SqliteDialect.quote_path(path);
+    let sql = if sample {
+        format!("SELECT * FROM {table} ORDER BY random()")
+    } else {
+        format!("SELECT * FROM {table}")
+    };
+    query_read_only(pool, &sql, &[], rows, timeout).await
+}
+
+#[async_trait]
+impl connector::Trait for SqliteConnector {
+    async fn create_record<D: Database>(
+        database: D,
+        conn: connector::Create,
+    ) -> Result<connector::Details> {
+        let id = create_id(8).await;
+        let connector_details = connector::Details {
+            id,
+            name: conn.name,
+            r#type: conn.r#type,
+            connection_string: conn.connection_string,
+            settings: conn.settings,
+            secret_ref: conn.secret_ref,
+        };
+        database.create_connector(connector_details.clone()).await?;
+        Ok(connector_details)
+    }
+
+    async fn get_available_datasets(
+        &self,
+        schema: Option<&str>,
+    ) -> Result<Vec<connector::AvailableDataset>> {
+        // Attached databases are not opened, so everything is in "main"
+        if schema.is_some_and(|schema| schema != "main") {
+            return Ok(Vec::new());
+        }
+
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT name, type FROM sqlite_master
+             WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%'
+             ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        // Row counts lead the stat column, and are only known once ANALYZE has run
+        let has_stats: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'sqlite_stat1'",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        let estimates: Vec<(String, i64)> = if has_stats.is_some() {
+            sqlx::query_as("SELECT tbl, max(CAST(stat AS INTEGER)) FROM sqlite_stat1 GROUP BY tbl")
+                .fetch_all(&self.pool)
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        Ok(rows
+            .into_iter()
+            .map(|(name, r#type)| connector::AvailableDataset {
+                estimated_rows: estimates
+                    .iter()
+                    .find(|(table, _)| *table == name)
+                    .map(|(_, rows)| *rows as u64),
+                kind: if r#type == "view" {
+                    connector::Kind::View
+                } else {
+                    connector::Kind::Table
+                },
+                comment: None,
+                path: name,
+            })
+            .collect())
+    }
+
+    async fn schemas(&self) -> Result<Vec<connector::SchemaAccess>> {
+        let datasets: i64 = sqlx::query_scalar(
+            "SELECT count(*) FROM sqlite_master
+             WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%'",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        // Opening the file grants access to every table in it
+        Ok(vec![connector::SchemaAccess {
+            name: String::from("main"),
+            datasets: datasets as u64,
+            readable: datasets as u64,
+        }])
+    }
+
+    async fn get_data_info(&self, path: &str) -> Result<connector::DataInfo> {
+        let rows = sqlx::query(
+            "SELECT name, type, \"notnull\", dflt_value, pk
+             FROM pragma_table_info(?1) ORDER BY cid",
+        )
+        .bind(path)
+        .fetch_all(&self.pool)
+        .await?;
+
+        if rows.is_empty() {
+            return Err(connector::NotFound(path.to_string()).into());
+        }
+
+        let foreign_keys: Vec<(String, String, Option<String>)> =
+            sqlx::query_as("SELECT \"from\", \"table\", \"to\" FROM pragma_foreign_key_list(?1)")
+                .bind(path)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let columns =
+            rows.into_iter()
+                .map(|row| {
+                    let name: String = row.get("name");
+                    let data_type: String = row.get("type");
+                    // A missing target column refers to the other table's primary key
+                    let foreign_key = foreign_keys.iter().find(|(from, _, _)| *from == name).map(
+                        |(_, table, to)| ForeignKey {
+                            table: table.clone(),
+                            column: to.clone().unwrap_or_default(),
+                        },
+                    );
+
+                    SchemaColumn {
+                        data_type: data_type.to_lowercase(),
+                        nullable: row.get::<i64, _>("notnull") == 0,
+                        default: row.get("dflt_value"),
+                        primary_key: row.get::<i64, _>("pk") > 0,
+                        foreign_key,
+                        comment: None,
+                        name,
+                    }
+                })
+                .collect();
+
+        Ok(connector::DataInfo {
+            path: path.to_string(),
+            schema: Schema { columns },
+        })
+    }
+
+    fn dialect(&self) -> &dyn Dialect {
+        &SqliteDialect
+    }
+
+    async fn query(
+        &self,
+        sql: &str,
+        params: &[Value],
+        limit: u64,
+    ) -> Result<connector::QueryResult> {
+        query_read_only(&self.pool, sql, params, limit, self.statement_timeout).await
+    }
+
+    async fn preview(&self, path: &str, rows: u64, sample: bool) -> Result<connector::QueryResult> {
+        // Resolve the path first so a missing table is reported as not found
+        let data_info = self.get_data_info(path).await?;
+        preview_table(
+            &self.pool,
+            &data_info.path,
+            rows,
+            sample,
+            self.statement_timeout,
+        )
+        .await
+    }
+
+    async fn profile(&self, path: &str, schema: &Schema) -> Result<Profile> {
+        profile_read_only(
+            &self.pool,
+            &SqliteDialect.quote_path(path),
+            schema,
+            self.statement_timeout,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    // One connection, like the file connector, so a connection that was not
+    // restored would lose the table
+    async fn pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        pool.execute("CREATE TABLE x (id INTEGER); INSERT INTO x VALUES (1)")
+            .await
+            .unwrap();
+        pool
+    }
+
+    async fn query(pool: &SqlitePool, sql: &str) -> Result<connector::QueryResult> {
+        query_read_only(pool, sql, &[], 10, Duration::from_secs(5)).await
+    }
+
+    #[tokio::test]
+    async fn rejects_statements_after_the_query() {
+        let pool = pool().await;
+        let sql = "SELECT 1) AS q; PRAGMA query_only = OFF; DROP TABLE x; SELECT * FROM (SELECT 1";
+        assert!(query(&pool, sql).await.is_err());
+
+        let result = query(&pool, "SELECT id FROM x").await.unwrap();
+        assert_eq!(result.rows, vec![vec![Value::from(1)]]);
+    }
+
+    #[tokio::test]
+    async fn checks_for_a_single_statement() {
+        let pool = pool().await;
+        let mut read_only = ReadOnly::acquire(&pool, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(read_only
+            .check_single_statement("SELECT 1; SELECT 2")
+            .await
+            .is_err());
+        assert!(read_only
+            .check_single_statement("SELECT 1;  \n")
+            .await
+            .is_ok());
+        read_only.release().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn denies_attach_and_pragmas() {
+        let pool = pool().await;
+        assert!(query(&pool, "SELECT * FROM pragma_table_info('x')")
+            .await
+            .is_err());
+        assert!(query(&pool, "ATTACH DATABASE ':memory:' AS other")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn connection_is_writable_after_a_query() {
+        let pool = pool().await;
+        query(&pool, "SELECT id FROM x").await.unwrap();
+        assert!(query(&pool, "DELETE FROM x").await.is_err());
+
+        pool.execute("INSERT INTO x VALUES (2)").await.unwrap();
+        let result = query(&pool, "SELECT count(*) AS n FROM x").await.unwrap();
+        assert_eq!(result.rows, vec![vec![Value::from(2)]]);
+    }
+
+    #[tokio::test]
+    async fn dropped_query_closes_the_connection() {
+        let pool = pool().await;
+        let read_only = ReadOnly::acquire(&pool, Duration::ZERO).await.unwrap();
+        drop(read_only);
+
+        // The replacement connection is writable and not interrupted
+        pool.execute("CREATE TABLE y (id INTEGER)").await.unwrap();
+    }
+}
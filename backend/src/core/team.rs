@@ -16,18 +16,14 @@ pub struct Create {
 }
 
 impl Team {
-    pub async fn create<T: Database>(database: T, team: &Create) -> Result<()> {
-        let id = create_id(30).await;
-        let db_resp = database
-            .create_team(&Team {
-                id,
-                name: team.name.clone(),
-                active: true,
-            })
-            .await;
-
-        match db_resp {
-            Ok(()) => Ok(()),
+    pub async fn create<T: Database>(database: T, team: &Create) -> Result<Self> {
+        let team = Team {
+            id: create_id(30).await,
+            name: team.name.clone(),
+            active: true,
+        };
+        match database.create_team(&team).await {
+            Ok(()) => Ok(team),
             Err(_) => Err(anyhow!("failed to create team")),
         }
     }
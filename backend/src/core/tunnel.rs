@@ -0,0 +1,525 @@
+use crate::core::connector::{ConnectionError, ErrorKind};
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use ssh2::{Channel, ErrorCode, HashType, Session};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+// Longest sleep of the forwarding loop while no data is moving
+const MAX_IDLE_WAIT: Duration = Duration::from_millis(20);
+const BUFFER_SIZE: usize = 32 * 1024;
+// libssh2's LIBSSH2_ERROR_EAGAIN, returned by non-blocking calls that must be repeated
+const EAGAIN: i32 = -37;
+
+/// SSH bastion a connector's database is reached through.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SshTunnel {
+    pub host: String,
+    // Defaults to 22
+    pub port: Option<u16>,
+    pub user: String,
+    // Reference to the secret holding the PEM private key, e.g. "secret://bastion/key".
+    // Holds the key itself once resolved, and is never stored that way.
+    pub private_key: String,
+    // Expected host key, as printed by `ssh-keygen -l`, e.g. "SHA256:...". Required.
+    pub host_key_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TunnelState {
+    Connected,
+    // The SSH session dropped and is being opened again
+    Reconnecting,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TunnelStatus {
+    pub state: TunnelState,
+    // Why the last session dropped or could not be opened
+    pub error: Option<String>,
+    pub reconnects: u32,
+}
+
+/// Local port forwarded through an SSH session to a remote address. A thread
+/// owns the session and its channels, and reopens the session when it drops.
+/// The thread stops when the tunnel is closed or dropped.
+#[derive(Debug)]
+pub struct Tunnel {
+    pub local_port: u16,
+    status: Arc<Mutex<TunnelStatus>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl Tunnel {
+    /// Opens an SSH session and forwards a local port to `remote_host:remote_port`,
+    /// as seen from the bastion.
+    pub async fn open(config: &SshTunnel, remote_host: &str, remote_port: u16) -> Result<Self> {
+        let connect_config = config.clone();
+        let session = tokio::task::spawn_blocking(move || connect(&connect_config)).await??;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+
+        let tunnel = Tunnel {
+            local_port: listener.local_addr()?.port(),
+            status: Arc::new(Mutex::new(TunnelStatus {
+                state: TunnelState::Connected,
+                error: None,
+                reconnects: 0,
+            })),
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+        info!(
+            "tunnel: 127.0.0.1:{} -> {remote_host}:{remote_port} via {}",
+            tunnel.local_port, config.host
+        );
+
+        let forwarder = Forwarder {
+            config: config.clone(),
+            remote_host: remote_host.to_string(),
+            remote_port,
+            listener,
+            status: tunnel.status.clone(),
+            stop: tunnel.stop.clone(),
+        };
+        thread::Builder::new()
+            .name(format!("tunnel-{}", tunnel.local_port))
+            .spawn(move || forwarder.run(session))?;
+        Ok(tunnel)
+    }
+
+    pub fn status(&self) -> TunnelStatus {
+        self.status
+            .lock()
+            .expect("tunnel status lock poisoned")
+            .clone()
+    }
+
+    pub fn close(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn tunnel_error(kind: ErrorKind, message: String) -> anyhow::Error {
+    ConnectionError::new(kind, message).into()
+}
+
+// Opens and authenticates a blocking session to the bastion
+fn connect(config: &SshTunnel) -> Result<Session> {
+    let port = config.port.unwrap_or(22);
+    let address = (config.host.as_str(), port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .ok_or_else(|| {
+            tunnel_error(
+                ErrorKind::Unreachable,
+                format!("SSH host {} could not be resolved", config.host),
+            )
+        })?;
+    let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).map_err(|e| {
+        tunnel_error(
+            ErrorKind::Unreachable,
+            format!("SSH host {}:{port}: {e}", config.host),
+        )
+    })?;
+
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(CONNECT_TIMEOUT.as_millis() as u32);
+    session.handshake().map_err(|e| {
+        tunnel_error(
+            ErrorKind::Tunnel,
+            format!("SSH handshake with {} failed: {e}", config.host),
+        )
+    })?;
+
+    // Tunnels saved before the fingerprint was required are refused too
+    let Some(expected) = &config.host_key_fingerprint else {
+        return Err(tunnel_error(
+            ErrorKind::Tunnel,
+            format!(
+                "SSH host key of {} can not be verified, set host_key_fingerprint",
+                config.host
+            ),
+        ));
+    };
+    let actual = session
+        .host_key_hash(HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)))
+        .unwrap_or_default();
+    if actual != expected.trim() {
+        return Err(tunnel_error(
+            ErrorKind::Tunnel,
+            format!(
+                "SSH host key of {} does not match, got {actual}",
+                config.host
+            ),
+        ));
+    }
+
+    session
+        .userauth_pubkey_memory(&config.user, None, &config.private_key, None)
+        .map_err(|e| {
+            tunnel_error(
+                ErrorKind::Authentication,
+                format!("SSH authentication as {} failed: {e}", config.user),
+            )
+        })?;
+    session.set_keepalive(false, KEEPALIVE_INTERVAL.as_secs() as u32);
+    Ok(session)
+}
+
+fn is_eagain(error: &ssh2::Error) -> bool {
+    error.code() == ErrorCode::Session(EAGAIN)
+}
+
+// Copies data between a local connection and its channel in both directions
+struct Forward {
+    local: TcpStream,
+    channel: Channel,
+    // Data read from one side that is not yet written to the other
+    to_remote: Vec<u8>,
+    to_local: Vec<u8>,
+    local_eof: bool,
+    remote_eof: bool,
+    sent_eof: bool,
+}
+
+impl Forward {
+    // Moves whatever data is ready. Returns whether any data moved.
+    fn pump(&mut self, buffer: &mut [u8]) -> io::Result<bool> {
+        let mut moved = false;
+
+        if !self.local_eof && self.to_remote.is_empty() {
+            match self.local.read(buffer) {
+                Ok(0) => self.local_eof = true,
+                Ok(n) => self.to_remote.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.to_remote.is_empty() {
+            match self.channel.write(&self.to_remote) {
+                Ok(n) => {
+                    self.to_remote.drain(..n);
+                    moved = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if self.local_eof && self.to_remote.is_empty() && !self.sent_eof {
+            match self.channel.send_eof() {
+                Ok(()) => self.sent_eof = true,
+                Err(e) if is_eagain(&e) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if !self.remote_eof && self.to_local.is_empty() {
+            match self.channel.read(buffer) {
+                Ok(0) => self.remote_eof = self.channel.eof(),
+                Ok(n) => self.to_local.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.to_local.is_empty() {
+            match self.local.write(&self.to_local) {
+                Ok(n) => {
+                    self.to_local.drain(..n);
+                    moved = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if self.remote_eof && self.to_local.is_empty() {
+            let _ = self.local.shutdown(Shutdown::Write);
+        }
+        Ok(moved)
+    }
+
+    fn is_done(&self) -> bool {
+        self.local_eof && self.remote_eof && self.to_remote.is_empty() && self.to_local.is_empty()
+    }
+}
+
+struct Forwarder {
+    config: SshTunnel,
+    remote_host: String,
+    remote_port: u16,
+    listener: TcpListener,
+    status: Arc<Mutex<TunnelStatus>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Forwarder {
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    fn set_status(&self, state: TunnelState, error: Option<String>) {
+        let mut status = self.status.lock().expect("tunnel status lock poisoned");
+        if state == TunnelState::Reconnecting && status.state == TunnelState::Connected {
+            status.reconnects += 1;
+        }
+        status.state = state;
+        status.error = error;
+    }
+
+    fn run(self, mut session: Session) {
+        loop {
+            let error = self.forward(&session);
+            if self.stopped() {
+                break;
+            }
+            warn!("tunnel {}: session dropped: {error}", self.config.host);
+            self.set_status(TunnelState::Reconnecting, Some(error));
+
+            session = match self.reconnect() {
+                Some(session) => session,
+                None => break,
+            };
+            info!("tunnel {}: reconnected", self.config.host);
+            self.set_status(TunnelState::Connected, None);
+        }
+        info!("tunnel {}: closed", self.config.host);
+    }
+
+    // Opens a new session, backing off between attempts. Returns `None` if
+    // the tunnel is closed meanwhile.
+    fn reconnect(&self) -> Option<Session> {
+        let mut delay = Duration::from_secs(1);
+        loop {
+            let deadline = Instant::now() + delay;
+            while Instant::now() < deadline {
+                if self.stopped() {
+                    return None;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            match connect(&self.config) {
+                Ok(session) => return Some(session),
+                Err(e) => {
+                    warn!("tunnel {}: reconnect failed: {e}", self.config.host);
+                    self.set_status(TunnelState::Reconnecting, Some(e.to_string()));
+                }
+            }
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    // Forwards connections until the session fails or the tunnel is closed.
+    // Returns why the session failed.
+    fn forward(&self, session: &Session) -> String {
+        session.set_blocking(false);
+        let mut pending: Vec<TcpStream> = Vec::new();
+        let mut forwards: Vec<Forward> = Vec::new();
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let mut last_keepalive = Instant::now();
+        let mut idle_wait = Duration::from_millis(1);
+
+        let failure = loop {
+            if self.stopped() {
+                break String::from("closed");
+            }
+            let mut moved = false;
+
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    let _ = stream.set_nodelay(true);
+                    if stream.set_nonblocking(true).is_ok() {
+                        pending.push(stream);
+                    }
+                    moved = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => warn!("tunnel {}: accept failed: {e}", self.config.host),
+            }
+
+            // Channels open over several calls while the session is non-blocking
+            let mut check_session = false;
+            let mut waiting = Vec::new();
+            for stream in pending.drain(..) {
+                match session.channel_direct_tcpip(&self.remote_host, self.remote_port, None) {
+                    Ok(channel) => forwards.push(Forward {
+                        local: stream,
+                        channel,
+                        to_remote: Vec::new(),
+                        to_local: Vec::new(),
+                        local_eof: false,
+                        remote_eof: false,
+                        sent_eof: false,
+                    }),
+                    Err(e) if is_eagain(&e) => waiting.push(stream),
+                    Err(e) => {
+                        // The remote may refuse the connection, or the session may be gone
+                        warn!(
+                            "tunnel {}: forwarding to {}:{} failed: {e}",
+                            self.config.host, self.remote_host, self.remote_port
+                        );
+                        check_session = true;
+                    }
+                }
+            }
+            pending = waiting;
+
+            let mut open = Vec::with_capacity(forwards.len());
+            for mut forward in forwards.drain(..) {
+                match forward.pump(&mut buffer) {
+                    Ok(forward_moved) => {
+                        moved |= forward_moved;
+                        if forward.is_done() {
+                            self.release(session, forward);
+                        } else {
+                            open.push(forward);
+                        }
+                    }
+                    Err(_) => {
+                        check_session = true;
+                        self.release(session, forward);
+                    }
+                }
+            }
+            forwards = open;
+
+            if check_session || last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+                match session.keepalive_send() {
+                    Ok(_) => last_keepalive = Instant::now(),
+                    Err(e) if is_eagain(&e) => {}
+                    Err(e) => break e.to_string(),
+                }
+            }
+
+            if moved {
+                idle_wait = Duration::from_millis(1);
+            } else {
+                thread::sleep(idle_wait);
+                idle_wait = (idle_wait * 2).min(MAX_IDLE_WAIT);
+            }
+        };
+
+        for forward in forwards {
+            self.release(session, forward);
+        }
+        failure
+    }
+
+    // Closes a channel. Blocks briefly, since libssh2 can not free a channel
+    // that is still closing on a non-blocking session.
+    fn release(&self, session: &Session, mut forward: Forward) {
+        session.set_blocking(true);
+        session.set_timeout(1_000);
+        let _ = forward.channel.close();
+        drop(forward);
+        session.set_timeout(CONNECT_TIMEOUT.as_millis() as u32);
+        session.set_blocking(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgConnectOptions;
+    use sqlx::{ConnectOptions, Connection};
+    use std::env;
+
+    // The bastion service in docker-compose.yml, with the private key of
+    // local/bastion/bastion.pub next to it. The host key is generated when the
+    // container starts, so its fingerprint is passed in
+    // BASTION_HOST_KEY_FINGERPRINT.
+    fn bastion(fingerprint: Option<&str>) -> SshTunnel {
+        let key = concat!(env!("CARGO_MANIFEST_DIR"), "/../local/bastion/bastion");
+        SshTunnel {
+            host: String::from("localhost"),
+            port: Some(2222),
+            user: String::from("bastion"),
+            private_key: std::fs::read_to_string(key).unwrap(),
+            host_key_fingerprint: fingerprint.map(String::from),
+        }
+    }
+
+    async fn open_error(config: SshTunnel) -> ConnectionError {
+        let error = Tunnel::open(&config, "data-warehouse", 5432)
+            .await
+            .err()
+            .unwrap();
+        error.downcast_ref::<ConnectionError>().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn classifies_bastions_that_can_not_be_reached() {
+        let config = |port| SshTunnel {
+            host: String::from("127.0.0.1"),
+            port: Some(port),
+            user: String::from("bastion"),
+            private_key: String::new(),
+            host_key_fingerprint: None,
+        };
+        // Nothing listens on port 1
+        assert_eq!(open_error(config(1)).await.kind, ErrorKind::Unreachable);
+
+        // Accepts the connection but never speaks SSH
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            drop(stream);
+        });
+        let error = open_error(config(port)).await;
+        assert_eq!(error.kind, ErrorKind::Tunnel);
+        assert!(error.message.contains("handshake"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the bastion and data-warehouse services from docker-compose.yml"]
+    async fn forwards_to_the_warehouse() {
+        let fingerprint = env::var("BASTION_HOST_KEY_FINGERPRINT").unwrap();
+        let tunnel = Tunnel::open(&bastion(Some(&fingerprint)), "data-warehouse", 5432)
+            .await
+            .unwrap();
+        let mut conn = PgConnectOptions::new()
+            .host("127.0.0.1")
+            .port(tunnel.local_port)
+            .username("user")
+            .password("password")
+            .database("warehouse")
+            .connect()
+            .await
+            .unwrap();
+        let one: i32 = sqlx::query_scalar("SELECT 1")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(one, 1);
+        conn.close().await.unwrap();
+        assert_eq!(tunnel.status().state, TunnelState::Connected);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the bastion service from docker-compose.yml"]
+    async fn refuses_an_unexpected_host_key() {
+        let config = bastion(Some("SHA256:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"));
+        let error = open_error(config).await;
+        assert_eq!(error.kind, ErrorKind::Tunnel);
+        assert!(error.message.contains("does not match"));
+    }
+}
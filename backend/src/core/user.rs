@@ -1,4 +1,4 @@
-use crate::core::create_id;
+use crate::core::{create_id, Role};
 use crate::data::Database;
 use anyhow::Result;
 use argon2::{
@@ -6,6 +6,7 @@ use argon2::{
     Argon2,
 };
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 // TODO: Add orgs property which stores a list of org ids
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -14,7 +15,7 @@ pub struct User {
     pub email: String,
     pub first_name: String,
     pub last_name: String,
-    pub r#type: String,
+    pub r#type: Role,
     pub is_active: bool,
     pub hash: String,
 }
@@ -29,10 +30,15 @@ pub struct Create {
     pub email: String,
     pub first_name: String,
     pub last_name: String,
-    pub r#type: String,
+    pub r#type: Role,
     pub password: String,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CreateServiceAccount {
+    pub name: String,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Email {
     pub email: String,
@@ -45,17 +51,67 @@ pub struct Profile {
     pub email: String,
     pub first_name: String,
     pub last_name: String,
-    pub r#type: String,
+    pub r#type: Role,
     pub is_active: bool,
 }
 
+/// Returned when creating a user with an email that belongs to another user.
+#[derive(Debug, Clone)]
+pub struct EmailTaken(pub String);
+
+impl fmt::Display for EmailTaken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Email '{}' is already in use", self.0)
+    }
+}
+
+impl std::error::Error for EmailTaken {}
+
 impl User {
     pub async fn create<T: Database>(database: T, user: &Create) -> Result<()> {
+        if User::from_email(database.clone(), &user.email)
+            .await
+            .is_ok()
+        {
+            return Err(EmailTaken(user.email.clone()).into());
+        }
         let user_id = create_id(10).await;
         let new_user = User::from_create_user(user, &user_id, true);
         database.create_user(&new_user).await
     }
 
+    /// Creates a user with a random password that is never returned, for users
+    /// that sign in some other way.
+    pub async fn create_without_password<T: Database>(
+        database: T,
+        mut create_user: Create,
+    ) -> Result<User> {
+        let user_id = create_id(10).await;
+        create_user.password = create_id(40).await;
+        let user = User::from_create_user(&create_user, &user_id, true);
+        database.create_user(&user).await?;
+        Ok(user)
+    }
+
+    /// Creates a user to own API keys, which can not log in.
+    pub async fn create_service_account<T: Database>(database: T, name: &str) -> Result<User> {
+        let create_user = Create {
+            email: format!("{}@service-account", create_id(10).await.to_lowercase()),
+            first_name: name.to_string(),
+            last_name: String::new(),
+            r#type: Role::ServiceAccount,
+            password: String::new(),
+        };
+        User::create_without_password(database, create_user).await
+    }
+
+    pub async fn set_role<T: Database>(database: T, id: &str, role: Role) -> Result<User> {
+        let mut user = database.get_user_by_id(id).await?;
+        user.r#type = role;
+        database.create_user(&user).await?;
+        Ok(user)
+    }
+
     pub async fn from_id<T: Database>(database: T, id: &str) -> Result<User> {
         database.get_user_by_id(id).await
     }
@@ -78,7 +134,7 @@ impl User {
             email: create_user.email.to_string(),
             first_name: create_user.first_name.to_string(),
             last_name: create_user.last_name.to_string(),
-            r#type: create_user.r#type.to_string(),
+            r#type: create_user.r#type,
             is_active,
             hash: password_hash,
         }
@@ -1,12 +1,17 @@
-use crate::core::{connector, Dataset, Org, Session, Team, User};
+use crate::core::{
+    connector, create_id, profile::Profile, user, ApiKey, Dataset, Identity, Org, Role,
+    SchemaVersion, Session, Team, User,
+};
 use anyhow::Result;
 use async_trait::async_trait;
+use tracing::{info, warn};
 
 #[async_trait]
-pub trait Database: Send + Sync + Clone + SessionStore + UserStore + 'static {}
+pub trait Database: Send + Sync + Clone + SessionStore + UserStore + ApiKeyStore + 'static {}
 
 #[async_trait]
 pub trait UserStore: Send + Sync + Clone + 'static {
+    // Fails with `EmailTaken` if the email belongs to another user
     async fn create_user(&self, user: &User) -> Result<()>;
     async fn get_user_by_email(&self, email: &str) -> Result<User>;
     async fn get_user_by_id(&self, id: &str) -> Result<User>;
@@ -16,16 +21,75 @@ pub trait UserStore: Send + Sync + Clone + 'static {
     async fn create_team(&self, org: &Team) -> Result<()>;
     async fn get_teams(&self) -> Result<Vec<Team>>;
     async fn get_team_by_id(&self, id: &str) -> Result<Team>;
-    // Encrypt + Salt connection_string
+    async fn get_team_ids_by_user(&self, user_id: &str) -> Result<Vec<String>>;
+    // Replaces all of the user's team memberships
+    async fn set_team_ids_for_user(&self, user_id: &str, team_ids: &[String]) -> Result<()>;
+    async fn create_identity(&self, identity: &Identity) -> Result<()>;
+    async fn get_identity(&self, issuer: &str, subject: &str) -> Result<Identity>;
+    // connection_string is stored as given, already encrypted by the caller
     async fn create_connector(&self, conn: connector::Details) -> Result<()>;
     async fn get_connectors(&self) -> Result<Vec<connector::Details>>;
+    async fn get_connector_by_id(&self, id: &str) -> Result<connector::Details>;
+    async fn update_connector(&self, conn: connector::Details) -> Result<()>;
+    async fn delete_connector(&self, id: &str) -> Result<()>;
     async fn create_dataset(&self, dataset: Dataset) -> Result<()>;
     async fn get_datasets(&self) -> Result<Vec<Dataset>>;
+    async fn get_dataset_by_id(&self, id: &str) -> Result<Dataset>;
+    async fn update_dataset_profile(&self, id: &str, profile: &Profile) -> Result<()>;
+    // Writes schema, schema_version, status and last_synced_at only while the
+    // stored schema_version is still `expected_version`; returns false otherwise
+    async fn update_dataset_schema(&self, dataset: &Dataset, expected_version: u32)
+        -> Result<bool>;
+    // Also deletes the dataset's schema history
+    async fn delete_dataset(&self, id: &str) -> Result<()>;
+    async fn create_schema_version(&self, version: SchemaVersion) -> Result<()>;
+    async fn get_schema_versions(&self, dataset_id: &str) -> Result<Vec<SchemaVersion>>;
 }
 
 #[async_trait]
 pub trait SessionStore: Send + Sync + Clone + 'static {
     async fn get_session_by_id(&self, id: &str) -> Result<Session>;
-    async fn create_session(&self, user: Option<&'life1 User>, session_id: &str) -> Result<()>;
+    async fn create_session(&self, session: &Session) -> Result<()>;
+    async fn update_session(&self, session: &Session) -> Result<()>;
     async fn delete_session(&self, session_id: &str) -> Result<()>;
+    // Deletes sessions that expired by `now`, returning how many were deleted
+    async fn delete_expired_sessions(&self, now: u64) -> Result<u64>;
+}
+
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync + Clone + 'static {
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<()>;
+    async fn get_api_key_by_id(&self, id: &str) -> Result<ApiKey>;
+    async fn get_api_keys_by_user(&self, user_id: &str) -> Result<Vec<ApiKey>>;
+    async fn update_api_key(&self, api_key: &ApiKey) -> Result<()>;
+    async fn delete_api_key(&self, id: &str) -> Result<()>;
+}
+
+/// Seeds the default admin user if it does not exist yet.
+pub async fn create_admin_user<D: Database>(database: D) -> Result<()> {
+    let admin_user = User::from_email(database.clone(), "test@example.com").await;
+
+    if admin_user.is_ok() {
+        info!("db init: admin user exists.");
+    } else {
+        info!("db init: creating admin user.");
+        let password = match std::env::var("ADMIN_PASSWORD") {
+            Ok(password) => password,
+            Err(_) => {
+                // Never logged or stored; set ADMIN_PASSWORD to sign in as the admin
+                warn!("db init: ADMIN_PASSWORD is not set, using a generated admin password.");
+                create_id(10).await
+            }
+        };
+        let admin_user = user::Create {
+            email: String::from("test@example.com"),
+            first_name: String::from("Admin"),
+            last_name: String::new(),
+            r#type: Role::PlatformAdmin,
+            password,
+        };
+
+        let _admin_user = User::create(database, &admin_user).await;
+    }
+    Ok(())
 }
@@ -1,9 +1,8 @@
 use crate::core::{
-    connector, create_id,
-    user::{self},
-    Dataset, Email, Org, Session, Team, User,
+    connector, profile::Profile, ApiKey, Dataset, Email, EmailTaken, Identity, Org, SchemaVersion,
+    Session, Team, User,
 };
-use crate::data::{Database, SessionStore, UserStore};
+use crate::data::{create_admin_user, ApiKeyStore, Database, SessionStore, UserStore};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use aws_config::meta::region::RegionProviderChain;
@@ -11,10 +10,11 @@ use aws_config::BehaviorVersion;
 use aws_sdk_dynamodb::error::{ProvideErrorMetadata, SdkError};
 use aws_sdk_dynamodb::types::{
     AttributeDefinition, AttributeValue as AV, BillingMode, GlobalSecondaryIndex, KeySchemaElement,
-    KeyType, Projection, ProjectionType, ScalarAttributeType,
+    KeyType, Projection, ProjectionType, ScalarAttributeType, TimeToLiveSpecification,
+    TimeToLiveStatus,
 };
 use aws_sdk_dynamodb::Client;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Debug, Clone)]
 pub struct Dynamodb {
@@ -133,6 +133,39 @@ impl Dynamodb {
         Ok(())
     }
 
+    // DynamoDB removes expired sessions itself once TTL is enabled on `expiry`
+    async fn enable_ttl(client: &Client, table_name: &str) -> Result<(), aws_sdk_dynamodb::Error> {
+        let response = client
+            .describe_time_to_live()
+            .table_name(table_name)
+            .send()
+            .await?;
+        let enabled = response
+            .time_to_live_description()
+            .and_then(|description| description.time_to_live_status())
+            .is_some_and(|status| {
+                matches!(
+                    status,
+                    TimeToLiveStatus::Enabled | TimeToLiveStatus::Enabling
+                )
+            });
+        if !enabled {
+            info!("enabling time to live on {table_name}");
+            client
+                .update_time_to_live()
+                .table_name(table_name)
+                .time_to_live_specification(
+                    TimeToLiveSpecification::builder()
+                        .attribute_name("expiry")
+                        .enabled(true)
+                        .build()?,
+                )
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+
     pub async fn new(dynamodb_endpoint: Option<&str>, table_name: &str) -> Result<Self> {
         let region_provider = RegionProviderChain::default_provider().or_else("eu-west-2");
 
@@ -168,30 +201,16 @@ impl Dynamodb {
             }
         }
 
+        if let Err(e) = Self::enable_ttl(&client, table_name).await {
+            warn!("table: could not enable time to live: {e}");
+        }
+
         let dynamodb = Dynamodb {
             client: client.clone(),
             table_name: table_name.into(),
         };
 
-        let admin_user = User::from_email(dynamodb.clone(), "test@example.com").await;
-
-        #[allow(clippy::useless_conversion)]
-        if admin_user.is_ok() {
-            info!("db init: admin user exists.");
-        } else {
-            info!("db init: creating admin user.");
-            let password = create_id(10).await;
-            let admin_user = user::Create {
-                email: String::from("test@example.com"),
-                first_name: String::from("Admin"),
-                last_name: String::from(password.clone()), // TODO: REMOVE: This is to reveal
-                // the admin password via the db
-                r#type: String::from("superadmin"),
-                password,
-            };
-
-            let _admin_user = User::create(dynamodb.clone(), &admin_user).await;
-        }
+        create_admin_user(dynamodb.clone()).await?;
         Ok(dynamodb)
     }
 }
@@ -199,21 +218,45 @@ impl Dynamodb {
 #[async_trait]
 impl UserStore for Dynamodb {
     async fn create_user(&self, user: &User) -> Result<()> {
-        // Create the USER item to insert
-        let mut item = std::collections::HashMap::new();
         let key = format!("{}{}", "USER#", user.id);
         let email = format!("{}{}", "EMAIL#", user.email);
         let r#type = format!("{}{}", "USERTYPE#", user.r#type);
 
+        // Create the EMAIL item first, unless the email belongs to another user
+        let mut email_item = std::collections::HashMap::new();
+        email_item.insert(String::from("PK"), AV::S(email.clone()));
+        email_item.insert(String::from("SK"), AV::S(email.clone()));
+        email_item.insert(String::from("GSI1PK"), AV::S(key.clone()));
+        email_item.insert(String::from("GSI1SK"), AV::S(key.clone()));
+
+        match self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(email_item))
+            .condition_expression("attribute_not_exists(PK) OR GSI1PK = :user")
+            .expression_attribute_values(":user", AV::S(key.clone()))
+            .send()
+            .await
+        {
+            Ok(_) => {}
+            Err(SdkError::ServiceError(e)) if e.err().is_conditional_check_failed_exception() => {
+                return Err(EmailTaken(user.email.clone()).into());
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        // Create the USER item to insert
+        let mut item = std::collections::HashMap::new();
         item.insert(String::from("PK"), AV::S(key.clone()));
-        item.insert(String::from("SK"), AV::S(key.clone()));
+        item.insert(String::from("SK"), AV::S(key));
         item.insert(String::from("GSI1PK"), AV::S(email.clone()));
-        item.insert(String::from("GSI1SK"), AV::S(email.clone()));
+        item.insert(String::from("GSI1SK"), AV::S(email));
         item.insert(String::from("GSI2PK"), AV::S(r#type.clone()));
         item.insert(String::from("GSI2SK"), AV::S(r#type));
         item.insert(String::from("first_name"), AV::S(user.first_name.clone()));
         item.insert(String::from("last_name"), AV::S(user.last_name.clone()));
-        item.insert(String::from("user_type"), AV::S(user.r#type.clone()));
+        item.insert(String::from("user_type"), AV::S(user.r#type.to_string()));
         item.insert(String::from("is_active"), AV::Bool(user.is_active));
         item.insert(String::from("hash"), AV::S(user.hash.clone()));
 
@@ -224,20 +267,6 @@ impl UserStore for Dynamodb {
             .send()
             .await?;
 
-        // Create the EMAIL item to insert
-        let mut email_item = std::collections::HashMap::new();
-        email_item.insert(String::from("PK"), AV::S(email.clone()));
-        email_item.insert(String::from("SK"), AV::S(email));
-        email_item.insert(String::from("GSI1PK"), AV::S(key.clone()));
-        email_item.insert(String::from("GSI1SK"), AV::S(key.clone()));
-
-        self.client
-            .put_item()
-            .table_name(&self.table_name)
-            .set_item(Some(email_item))
-            .send()
-            .await?;
-
         Ok(())
     }
 
@@ -391,6 +420,92 @@ impl UserStore for Dynamodb {
         }
     }
 
+    async fn get_team_ids_by_user(&self, user_id: &str) -> Result<Vec<String>> {
+        let query_output = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .index_name("GSI1")
+            .key_condition_expression("GSI1PK = :PK AND begins_with(GSI1SK, :SK)")
+            .expression_attribute_values(":PK", AV::S(format!("USER#{user_id}")))
+            .expression_attribute_values(":SK", AV::S("TEAM#".into()))
+            .send()
+            .await?;
+
+        Ok(query_output
+            .items()
+            .iter()
+            .filter_map(|item| item.get("GSI1SK")?.as_s().ok()?.strip_prefix("TEAM#"))
+            .map(str::to_string)
+            .collect())
+    }
+
+    async fn set_team_ids_for_user(&self, user_id: &str, team_ids: &[String]) -> Result<()> {
+        let current = self.get_team_ids_by_user(user_id).await?;
+        for team_id in current.iter().filter(|team_id| !team_ids.contains(team_id)) {
+            self.client
+                .delete_item()
+                .table_name(&self.table_name)
+                .key("PK", AV::S(format!("TEAM#{team_id}")))
+                .key("SK", AV::S(format!("USER#{user_id}")))
+                .send()
+                .await?;
+        }
+        for team_id in team_ids.iter().filter(|team_id| !current.contains(team_id)) {
+            let mut item = std::collections::HashMap::new();
+            item.insert(String::from("PK"), AV::S(format!("TEAM#{team_id}")));
+            item.insert(String::from("SK"), AV::S(format!("USER#{user_id}")));
+            item.insert(String::from("GSI1PK"), AV::S(format!("USER#{user_id}")));
+            item.insert(String::from("GSI1SK"), AV::S(format!("TEAM#{team_id}")));
+            self.client
+                .put_item()
+                .table_name(&self.table_name)
+                .set_item(Some(item))
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn create_identity(&self, identity: &Identity) -> Result<()> {
+        let mut item = std::collections::HashMap::new();
+        let key = format!("IDENTITY#{}#{}", identity.issuer, identity.subject);
+
+        item.insert(String::from("PK"), AV::S(key.clone()));
+        item.insert(String::from("SK"), AV::S(key.clone()));
+        item.insert(
+            String::from("GSI1PK"),
+            AV::S(format!("USER#{}", identity.user_id)),
+        );
+        item.insert(String::from("GSI1SK"), AV::S(key));
+        item.insert(String::from("issuer"), AV::S(identity.issuer.clone()));
+        item.insert(String::from("subject"), AV::S(identity.subject.clone()));
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get_identity(&self, issuer: &str, subject: &str) -> Result<Identity> {
+        let key = format!("IDENTITY#{issuer}#{subject}");
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(item.into()),
+            None => Err(anyhow!("identity not found")),
+        }
+    }
+
     async fn create_connector(&self, conn: connector::Details) -> Result<()> {
         // Create the item to insert
         let mut item = std::collections::HashMap::new();
@@ -412,6 +527,11 @@ impl UserStore for Dynamodb {
             String::from("connector_type"),
             AV::S(conn.r#type.to_string()),
         );
+        item.insert(
+            String::from("settings"),
+            AV::S(serde_json::to_string(&conn.settings)?),
+        );
+        item.insert(String::from("secret_ref"), AV::Bool(conn.secret_ref));
 
         self.client
             .put_item()
@@ -442,6 +562,40 @@ impl UserStore for Dynamodb {
         }
     }
 
+    async fn get_connector_by_id(&self, id: &str) -> Result<connector::Details> {
+        let key = format!("CONNECTOR#{id}");
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .send()
+            .await?;
+
+        match response.item {
+            Some(item) => Ok(item.into()),
+            None => Err(anyhow!("connector not found")),
+        }
+    }
+
+    async fn update_connector(&self, conn: connector::Details) -> Result<()> {
+        // put_item replaces the existing connector item
+        UserStore::create_connector(self, conn).await
+    }
+
+    async fn delete_connector(&self, id: &str) -> Result<()> {
+        let key = format!("CONNECTOR#{id}");
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .send()
+            .await?;
+        Ok(())
+    }
+
     async fn create_dataset(&self, dataset: Dataset) -> Result<()> {
         let mut item = std::collections::HashMap::new();
         let key = format!("{}{}", "DATASET#", dataset.id);
@@ -481,6 +635,24 @@ impl UserStore for Dynamodb {
             ),
         );
 
+        if let Some(profile) = &dataset.profile {
+            item.insert(
+                String::from("profile"),
+                AV::S(serde_json::to_string(profile)?),
+            );
+        }
+        item.insert(
+            String::from("schema_version"),
+            AV::N(dataset.schema_version.to_string()),
+        );
+        item.insert(String::from("status"), AV::S(dataset.status.to_string()));
+        if let Some(last_synced_at) = dataset.last_synced_at {
+            item.insert(
+                String::from("last_synced_at"),
+                AV::N(last_synced_at.to_string()),
+            );
+        }
+
         self.client
             .put_item()
             .table_name(&self.table_name)
@@ -510,6 +682,158 @@ impl UserStore for Dynamodb {
             None => Ok(Vec::new()),
         }
     }
+
+    async fn get_dataset_by_id(&self, id: &str) -> Result<Dataset> {
+        let key = format!("DATASET#{id}");
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .send()
+            .await?;
+
+        match response.item {
+            Some(item) => Ok(item.into()),
+            None => Err(anyhow!("dataset not found")),
+        }
+    }
+
+    async fn update_dataset_profile(&self, id: &str, profile: &Profile) -> Result<()> {
+        let key = format!("DATASET#{id}");
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .update_expression("SET profile = :profile")
+            .condition_expression("attribute_exists(PK)")
+            .expression_attribute_values(":profile", AV::S(serde_json::to_string(profile)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn update_dataset_schema(
+        &self,
+        dataset: &Dataset,
+        expected_version: u32,
+    ) -> Result<bool> {
+        let key = format!("DATASET#{}", dataset.id);
+        let mut update =
+            String::from("SET #schema = :schema, schema_version = :version, #status = :status");
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .condition_expression("schema_version = :expected")
+            .expression_attribute_names("#schema", "schema")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":schema", AV::S(serde_json::to_string(&dataset.schema)?))
+            .expression_attribute_values(":version", AV::N(dataset.schema_version.to_string()))
+            .expression_attribute_values(":status", AV::S(dataset.status.to_string()))
+            .expression_attribute_values(":expected", AV::N(expected_version.to_string()));
+        if let Some(last_synced_at) = dataset.last_synced_at {
+            update.push_str(", last_synced_at = :synced");
+            request =
+                request.expression_attribute_values(":synced", AV::N(last_synced_at.to_string()));
+        }
+
+        match request.update_expression(update).send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError(e)) if e.err().is_conditional_check_failed_exception() => {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete_dataset(&self, id: &str) -> Result<()> {
+        // The dataset item and its schema versions share a partition key
+        let key = format!("DATASET#{id}");
+        let mut exclusive_start_key = None;
+        loop {
+            let query_output = self
+                .client
+                .query()
+                .table_name(&self.table_name)
+                .key_condition_expression("PK = :PK")
+                .expression_attribute_values(":PK", AV::S(key.clone()))
+                .projection_expression("PK, SK")
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in query_output.items() {
+                self.client
+                    .delete_item()
+                    .table_name(&self.table_name)
+                    .set_key(Some(item.clone()))
+                    .send()
+                    .await?;
+            }
+
+            exclusive_start_key = query_output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn create_schema_version(&self, version: SchemaVersion) -> Result<()> {
+        let mut item = std::collections::HashMap::new();
+        let key = format!("DATASET#{}", version.dataset_id);
+        // Zero padded so versions sort numerically
+        let sort_key = format!("SCHEMAVERSION#{:010}", version.version);
+
+        item.insert(String::from("PK"), AV::S(key));
+        item.insert(String::from("SK"), AV::S(sort_key));
+        item.insert(String::from("version"), AV::N(version.version.to_string()));
+        item.insert(
+            String::from("schema"),
+            AV::S(serde_json::to_string(&version.schema)?),
+        );
+        item.insert(
+            String::from("changes"),
+            AV::S(serde_json::to_string(&version.changes)?),
+        );
+        item.insert(
+            String::from("recorded_at"),
+            AV::N(version.recorded_at.to_string()),
+        );
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get_schema_versions(&self, dataset_id: &str) -> Result<Vec<SchemaVersion>> {
+        let query_output = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("PK = :PK AND begins_with(SK, :SK)")
+            .expression_attribute_values(":PK", AV::S(format!("DATASET#{dataset_id}")))
+            .expression_attribute_values(":SK", AV::S("SCHEMAVERSION#".into()))
+            .send()
+            .await?;
+
+        match query_output.items {
+            Some(query_items) => Ok(query_items
+                .iter()
+                .map(|element| element.clone().into())
+                .collect::<Vec<SchemaVersion>>()),
+            None => Ok(Vec::new()),
+        }
+    }
 }
 
 #[async_trait]
@@ -536,19 +860,32 @@ impl SessionStore for Dynamodb {
         }
     }
 
-    async fn create_session(&self, user: Option<&'life1 User>, session_id: &str) -> Result<()> {
+    async fn create_session(&self, session: &Session) -> Result<()> {
         // Create the item to insert
         let mut item = std::collections::HashMap::new();
-        let key = format!("{}{}", "SESSION#", session_id);
+        let key = format!("{}{}", "SESSION#", session.id);
 
         item.insert(String::from("PK"), AV::S(key.clone()));
         item.insert(String::from("SK"), AV::S(key));
 
-        if let Some(u) = user {
-            let gsi1 = format!("{}{}", "USER#", u.id);
+        if let Some(user_id) = &session.user_id {
+            let gsi1 = format!("{}{}", "USER#", user_id);
             item.insert(String::from("GSI1PK"), AV::S(gsi1.clone()));
             item.insert(String::from("GSI1SK"), AV::S(gsi1));
         }
+        item.insert(
+            String::from("created_at"),
+            AV::N(session.created_at.to_string()),
+        );
+        item.insert(
+            String::from("last_seen_at"),
+            AV::N(session.last_seen_at.to_string()),
+        );
+        // The table's time to live attribute
+        item.insert(
+            String::from("expiry"),
+            AV::N(session.expires_at.to_string()),
+        );
 
         self.client
             .put_item()
@@ -570,4 +907,115 @@ impl SessionStore for Dynamodb {
             .await?;
         Ok(())
     }
+
+    async fn update_session(&self, session: &Session) -> Result<()> {
+        self.create_session(session).await
+    }
+
+    async fn delete_expired_sessions(&self, _now: u64) -> Result<u64> {
+        // Expired items are removed by time to live; reads check expiry
+        // themselves as removal can lag by a day or two
+        Ok(0)
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for Dynamodb {
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        let mut item = std::collections::HashMap::new();
+        let key = format!("APIKEY#{}", api_key.id);
+
+        item.insert(String::from("PK"), AV::S(key.clone()));
+        item.insert(String::from("SK"), AV::S(key.clone()));
+        // Lists a user's keys
+        item.insert(
+            String::from("GSI1PK"),
+            AV::S(format!("USER#{}", api_key.user_id)),
+        );
+        item.insert(String::from("GSI1SK"), AV::S(key));
+        item.insert(String::from("name"), AV::S(api_key.name.clone()));
+        item.insert(String::from("hash"), AV::S(api_key.hash.clone()));
+        item.insert(
+            String::from("scopes"),
+            AV::S(serde_json::to_string(&api_key.scopes)?),
+        );
+        item.insert(
+            String::from("created_at"),
+            AV::N(api_key.created_at.to_string()),
+        );
+        if let Some(expires_at) = api_key.expires_at {
+            item.insert(String::from("expires_at"), AV::N(expires_at.to_string()));
+        }
+        if let Some(last_used_at) = api_key.last_used_at {
+            item.insert(
+                String::from("last_used_at"),
+                AV::N(last_used_at.to_string()),
+            );
+        }
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get_api_key_by_id(&self, id: &str) -> Result<ApiKey> {
+        let key = format!("APIKEY#{id}");
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(item.into()),
+            None => Err(anyhow!("api key not found")),
+        }
+    }
+
+    async fn get_api_keys_by_user(&self, user_id: &str) -> Result<Vec<ApiKey>> {
+        let query_output = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .index_name("GSI1")
+            .key_condition_expression("GSI1PK = :PK AND begins_with(GSI1SK, :SK)")
+            .expression_attribute_values(":PK", AV::S(format!("USER#{user_id}")))
+            .expression_attribute_values(":SK", AV::S("APIKEY#".into()))
+            .send()
+            .await?;
+
+        match query_output.items {
+            Some(query_items) => {
+                let mut api_keys = query_items
+                    .iter()
+                    .map(|element| element.clone().into())
+                    .collect::<Vec<ApiKey>>();
+                api_keys.sort_by_key(|api_key| api_key.created_at);
+                Ok(api_keys)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn update_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        self.create_api_key(api_key).await
+    }
+
+    async fn delete_api_key(&self, id: &str) -> Result<()> {
+        let key = format!("APIKEY#{id}");
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .send()
+            .await?;
+        Ok(())
+    }
 }
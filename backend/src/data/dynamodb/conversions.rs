@@ -1,4 +1,6 @@
-use crate::core::{connector, Dataset, Email, Org, Session, Team, User};
+use crate::core::{
+    connector, ApiKey, Dataset, Email, Identity, Org, SchemaVersion, Session, Team, User,
+};
 use aws_sdk_dynamodb::types::AttributeValue as AV;
 use std::collections::HashMap;
 
@@ -14,7 +16,13 @@ impl From<HashMap<String, AV>> for User {
             first_name: value.get("first_name").unwrap().as_s().unwrap().to_string(),
             last_name: value.get("last_name").unwrap().as_s().unwrap().to_string(),
             is_active: *value.get("is_active").unwrap().as_bool().unwrap(),
-            r#type: value.get("user_type").unwrap().as_s().unwrap().to_string(),
+            r#type: value
+                .get("user_type")
+                .unwrap()
+                .as_s()
+                .unwrap()
+                .to_string()
+                .into(),
             hash: value.get("hash").unwrap().as_s().unwrap().to_string(),
         };
         user
@@ -39,6 +47,15 @@ impl From<HashMap<String, AV>> for Session {
         Session {
             id: split_at_hash(value.get("PK").unwrap().as_s().unwrap()).to_string(),
             user_id,
+            created_at: value
+                .get("created_at")
+                .map_or(0, |created_at| created_at.as_n().unwrap().parse().unwrap()),
+            last_seen_at: value.get("last_seen_at").map_or(0, |last_seen_at| {
+                last_seen_at.as_n().unwrap().parse().unwrap()
+            }),
+            expires_at: value
+                .get("expiry")
+                .map_or(0, |expiry| expiry.as_n().unwrap().parse().unwrap()),
         }
     }
 }
@@ -81,6 +98,14 @@ impl From<HashMap<String, AV>> for connector::Details {
                 .unwrap()
                 .to_string()
                 .into(),
+            // Connectors saved before settings existed use the defaults
+            settings: value
+                .get("settings")
+                .map(|settings| serde_json::from_str(settings.as_s().unwrap()).unwrap())
+                .unwrap_or_default(),
+            secret_ref: value
+                .get("secret_ref")
+                .is_some_and(|secret_ref| *secret_ref.as_bool().unwrap()),
         }
     }
 }
@@ -121,6 +146,77 @@ impl From<HashMap<String, AV>> for Dataset {
                     Some(serde_json::from_str(metadata_str).unwrap())
                 }
             }),
+            profile: value
+                .get("profile")
+                .map(|profile| serde_json::from_str(profile.as_s().unwrap()).unwrap()),
+            schema_version: value
+                .get("schema_version")
+                .map_or(0, |version| version.as_n().unwrap().parse().unwrap()),
+            status: value.get("status").map_or_else(Default::default, |status| {
+                status.as_s().unwrap().to_string().into()
+            }),
+            last_synced_at: value
+                .get("last_synced_at")
+                .map(|last_synced_at| last_synced_at.as_n().unwrap().parse().unwrap()),
+        }
+    }
+}
+
+impl From<HashMap<String, AV>> for SchemaVersion {
+    fn from(value: HashMap<String, AV>) -> Self {
+        SchemaVersion {
+            dataset_id: split_at_hash(value.get("PK").unwrap().as_s().unwrap()).to_string(),
+            version: value
+                .get("version")
+                .unwrap()
+                .as_n()
+                .unwrap()
+                .parse()
+                .unwrap(),
+            schema: serde_json::from_str(value.get("schema").unwrap().as_s().unwrap()).unwrap(),
+            changes: serde_json::from_str(value.get("changes").unwrap().as_s().unwrap()).unwrap(),
+            recorded_at: value
+                .get("recorded_at")
+                .unwrap()
+                .as_n()
+                .unwrap()
+                .parse()
+                .unwrap(),
+        }
+    }
+}
+
+impl From<HashMap<String, AV>> for ApiKey {
+    fn from(value: HashMap<String, AV>) -> Self {
+        ApiKey {
+            id: split_at_hash(value.get("PK").unwrap().as_s().unwrap()).to_string(),
+            name: value.get("name").unwrap().as_s().unwrap().to_string(),
+            user_id: split_at_hash(value.get("GSI1PK").unwrap().as_s().unwrap()).to_string(),
+            hash: value.get("hash").unwrap().as_s().unwrap().to_string(),
+            scopes: serde_json::from_str(value.get("scopes").unwrap().as_s().unwrap()).unwrap(),
+            created_at: value
+                .get("created_at")
+                .unwrap()
+                .as_n()
+                .unwrap()
+                .parse()
+                .unwrap(),
+            expires_at: value
+                .get("expires_at")
+                .map(|expires_at| expires_at.as_n().unwrap().parse().unwrap()),
+            last_used_at: value
+                .get("last_used_at")
+                .map(|last_used_at| last_used_at.as_n().unwrap().parse().unwrap()),
+        }
+    }
+}
+
+impl From<HashMap<String, AV>> for Identity {
+    fn from(value: HashMap<String, AV>) -> Self {
+        Identity {
+            issuer: value.get("issuer").unwrap().as_s().unwrap().to_string(),
+            subject: value.get("subject").unwrap().as_s().unwrap().to_string(),
+            user_id: split_at_hash(value.get("GSI1PK").unwrap().as_s().unwrap()).to_string(),
         }
     }
 }
@@ -0,0 +1,362 @@
+use crate::core::{
+    connector, profile::Profile, ApiKey, Dataset, EmailTaken, Identity, Org, SchemaVersion,
+    Session, Team, User,
+};
+use crate::data::{create_admin_user, ApiKeyStore, Database, SessionStore, UserStore};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Default)]
+struct Tables {
+    users: HashMap<String, User>,
+    // email -> user id
+    emails: HashMap<String, String>,
+    orgs: HashMap<String, Org>,
+    teams: HashMap<String, Team>,
+    // (team id, user id)
+    team_members: HashSet<(String, String)>,
+    // (issuer, subject) -> identity
+    identities: HashMap<(String, String), Identity>,
+    connectors: HashMap<String, connector::Details>,
+    datasets: HashMap<String, Dataset>,
+    // dataset id -> versions in ascending order
+    schema_versions: HashMap<String, Vec<SchemaVersion>>,
+    sessions: HashMap<String, Session>,
+    api_keys: HashMap<String, ApiKey>,
+}
+
+/// In-memory store used for tests and local development. Data is lost when
+/// the process exits.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    tables: Arc<RwLock<Tables>>,
+}
+
+impl Database for Memory {}
+
+impl Memory {
+    pub async fn new() -> Result<Self> {
+        let memory = Memory::default();
+        create_admin_user(memory.clone()).await?;
+        Ok(memory)
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&Tables) -> T) -> Result<T> {
+        let tables = self
+            .tables
+            .read()
+            .map_err(|_| anyhow!("memory: lock poisoned"))?;
+        Ok(f(&tables))
+    }
+
+    fn write<T>(&self, f: impl FnOnce(&mut Tables) -> T) -> Result<T> {
+        let mut tables = self
+            .tables
+            .write()
+            .map_err(|_| anyhow!("memory: lock poisoned"))?;
+        Ok(f(&mut tables))
+    }
+}
+
+#[async_trait]
+impl UserStore for Memory {
+    async fn create_user(&self, user: &User) -> Result<()> {
+        self.write(|tables| {
+            if tables
+                .emails
+                .get(&user.email)
+                .is_some_and(|user_id| *user_id != user.id)
+            {
+                return Err(EmailTaken(user.email.clone()).into());
+            }
+            tables.emails.insert(user.email.clone(), user.id.clone());
+            tables.users.insert(user.id.clone(), user.clone());
+            Ok(())
+        })?
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User> {
+        let user_id = self
+            .read(|tables| tables.emails.get(email).cloned())?
+            .ok_or_else(|| anyhow!("email not found"))?;
+        UserStore::get_user_by_id(self, &user_id).await
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<User> {
+        self.read(|tables| tables.users.get(id).cloned())?
+            .ok_or_else(|| anyhow!("user not found"))
+    }
+
+    async fn create_org(&self, org: &Org) -> Result<()> {
+        self.write(|tables| {
+            tables.orgs.insert(org.id.clone(), org.clone());
+        })
+    }
+
+    async fn get_org_by_id(&self, id: &str) -> Result<Org> {
+        self.read(|tables| tables.orgs.get(id).cloned())?
+            .ok_or_else(|| anyhow!("org not found"))
+    }
+
+    async fn delete_org(&self, id: &str) -> Result<()> {
+        self.write(|tables| {
+            tables.orgs.remove(id);
+        })
+    }
+
+    async fn create_team(&self, team: &Team) -> Result<()> {
+        self.write(|tables| {
+            tables.teams.insert(team.id.clone(), team.clone());
+        })
+    }
+
+    async fn get_teams(&self) -> Result<Vec<Team>> {
+        self.read(|tables| tables.teams.values().cloned().collect())
+    }
+
+    async fn get_team_by_id(&self, id: &str) -> Result<Team> {
+        self.read(|tables| tables.teams.get(id).cloned())?
+            .ok_or_else(|| anyhow!("team not found"))
+    }
+
+    async fn get_team_ids_by_user(&self, user_id: &str) -> Result<Vec<String>> {
+        self.read(|tables| {
+            tables
+                .team_members
+                .iter()
+                .filter(|(_, member)| member == user_id)
+                .map(|(team_id, _)| team_id.clone())
+                .collect()
+        })
+    }
+
+    async fn set_team_ids_for_user(&self, user_id: &str, team_ids: &[String]) -> Result<()> {
+        self.write(|tables| {
+            tables.team_members.retain(|(_, member)| member != user_id);
+            for team_id in team_ids {
+                tables
+                    .team_members
+                    .insert((team_id.clone(), user_id.to_string()));
+            }
+        })
+    }
+
+    async fn create_identity(&self, identity: &Identity) -> Result<()> {
+        self.write(|tables| {
+            tables.identities.insert(
+                (identity.issuer.clone(), identity.subject.clone()),
+                identity.clone(),
+            );
+        })
+    }
+
+    async fn get_identity(&self, issuer: &str, subject: &str) -> Result<Identity> {
+        self.read(|tables| {
+            tables
+                .identities
+                .get(&(issuer.to_string(), subject.to_string()))
+                .cloned()
+        })?
+        .ok_or_else(|| anyhow!("identity not found"))
+    }
+
+    async fn create_connector(&self, conn: connector::Details) -> Result<()> {
+        self.write(|tables| {
+            tables.connectors.insert(conn.id.clone(), conn);
+        })
+    }
+
+    async fn get_connectors(&self) -> Result<Vec<connector::Details>> {
+        self.read(|tables| tables.connectors.values().cloned().collect())
+    }
+
+    async fn get_connector_by_id(&self, id: &str) -> Result<connector::Details> {
+        self.read(|tables| tables.connectors.get(id).cloned())?
+            .ok_or_else(|| anyhow!("connector not found"))
+    }
+
+    async fn update_connector(&self, conn: connector::Details) -> Result<()> {
+        self.create_connector(conn).await
+    }
+
+    async fn delete_connector(&self, id: &str) -> Result<()> {
+        self.write(|tables| {
+            tables.connectors.remove(id);
+        })
+    }
+
+    async fn create_dataset(&self, dataset: Dataset) -> Result<()> {
+        self.write(|tables| {
+            tables.datasets.insert(dataset.id.clone(), dataset);
+        })
+    }
+
+    async fn get_datasets(&self) -> Result<Vec<Dataset>> {
+        self.read(|tables| tables.datasets.values().cloned().collect())
+    }
+
+    async fn get_dataset_by_id(&self, id: &str) -> Result<Dataset> {
+        self.read(|tables| tables.datasets.get(id).cloned())?
+            .ok_or_else(|| anyhow!("dataset not found"))
+    }
+
+    async fn update_dataset_profile(&self, id: &str, profile: &Profile) -> Result<()> {
+        self.write(|tables| {
+            if let Some(stored) = tables.datasets.get_mut(id) {
+                stored.profile = Some(profile.clone());
+            }
+        })
+    }
+
+    async fn update_dataset_schema(
+        &self,
+        dataset: &Dataset,
+        expected_version: u32,
+    ) -> Result<bool> {
+        self.write(|tables| match tables.datasets.get_mut(&dataset.id) {
+            Some(stored) if stored.schema_version == expected_version => {
+                stored.schema = dataset.schema.clone();
+                stored.schema_version = dataset.schema_version;
+                stored.status = dataset.status;
+                stored.last_synced_at = dataset.last_synced_at;
+                true
+            }
+            _ => false,
+        })
+    }
+
+    async fn delete_dataset(&self, id: &str) -> Result<()> {
+        self.write(|tables| {
+            tables.datasets.remove(id);
+            tables.schema_versions.remove(id);
+        })
+    }
+
+    async fn create_schema_version(&self, version: SchemaVersion) -> Result<()> {
+        self.write(|tables| {
+            let versions = tables
+                .schema_versions
+                .entry(version.dataset_id.clone())
+                .or_default();
+            versions.retain(|existing| existing.version != version.version);
+            versions.push(version);
+            versions.sort_by_key(|existing| existing.version);
+        })
+    }
+
+    async fn get_schema_versions(&self, dataset_id: &str) -> Result<Vec<SchemaVersion>> {
+        self.read(|tables| {
+            tables
+                .schema_versions
+                .get(dataset_id)
+                .cloned()
+                .unwrap_or_default()
+        })
+    }
+}
+
+#[async_trait]
+impl SessionStore for Memory {
+    async fn get_session_by_id(&self, id: &str) -> Result<Session> {
+        self.read(|tables| tables.sessions.get(id).cloned())?
+            .ok_or_else(|| anyhow!("session not found"))
+    }
+
+    async fn create_session(&self, session: &Session) -> Result<()> {
+        self.write(|tables| {
+            tables.sessions.insert(session.id.clone(), session.clone());
+        })
+    }
+
+    async fn update_session(&self, session: &Session) -> Result<()> {
+        self.create_session(session).await
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        self.write(|tables| {
+            tables.sessions.remove(session_id);
+        })
+    }
+
+    async fn delete_expired_sessions(&self, now: u64) -> Result<u64> {
+        self.write(|tables| {
+            let before = tables.sessions.len();
+            tables
+                .sessions
+                .retain(|_, session| session.expires_at > now);
+            (before - tables.sessions.len()) as u64
+        })
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for Memory {
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        self.write(|tables| {
+            tables.api_keys.insert(api_key.id.clone(), api_key.clone());
+        })
+    }
+
+    async fn get_api_key_by_id(&self, id: &str) -> Result<ApiKey> {
+        self.read(|tables| tables.api_keys.get(id).cloned())?
+            .ok_or_else(|| anyhow!("api key not found"))
+    }
+
+    async fn get_api_keys_by_user(&self, user_id: &str) -> Result<Vec<ApiKey>> {
+        self.read(|tables| {
+            let mut api_keys: Vec<ApiKey> = tables
+                .api_keys
+                .values()
+                .filter(|api_key| api_key.user_id == user_id)
+                .cloned()
+                .collect();
+            api_keys.sort_by_key(|api_key| api_key.created_at);
+            api_keys
+        })
+    }
+
+    async fn update_api_key(&self, api_key: &ApiKey) -> Result<()> {
+        self.create_api_key(api_key).await
+    }
+
+    async fn delete_api_key(&self, id: &str) -> Result<()> {
+        self.write(|tables| {
+            tables.api_keys.remove(id);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Role;
+    use argon2::{password_hash::PasswordHash, Argon2, PasswordVerifier};
+
+    #[tokio::test]
+    async fn seeds_the_admin_user() {
+        std::env::set_var("ADMIN_PASSWORD", "bootstrap-password");
+        let memory = Memory::new().await.unwrap();
+        std::env::remove_var("ADMIN_PASSWORD");
+
+        let admin = User::from_email(memory.clone(), "test@example.com")
+            .await
+            .unwrap();
+        assert_eq!(admin.r#type, Role::PlatformAdmin);
+        assert_eq!(admin.first_name, "Admin");
+        // The password is only kept as a hash
+        assert!(admin.last_name.is_empty());
+        let hash = PasswordHash::new(&admin.hash).unwrap();
+        assert!(Argon2::default()
+            .verify_password(b"bootstrap-password", &hash)
+            .is_ok());
+
+        create_admin_user(memory.clone()).await.unwrap();
+        let again = User::from_email(memory.clone(), "test@example.com")
+            .await
+            .unwrap();
+        assert_eq!(again.id, admin.id);
+        assert_eq!(memory.read(|tables| tables.users.len()).unwrap(), 1);
+    }
+}
@@ -1,5 +1,9 @@
 pub mod config;
 pub mod dynamodb;
+pub mod memory;
+pub mod postgres;
 
 pub use config::*;
 pub use dynamodb::*;
+pub use memory::*;
+pub use postgres::*;
@@ -0,0 +1,130 @@
+use crate::core::{connector, ApiKey, Dataset, Identity, Org, SchemaVersion, Session, Team, User};
+use sqlx::postgres::PgRow;
+use sqlx::types::Json;
+use sqlx::Row;
+
+impl From<PgRow> for User {
+    fn from(row: PgRow) -> Self {
+        User {
+            id: row.get("id"),
+            email: row.get("email"),
+            first_name: row.get("first_name"),
+            last_name: row.get("last_name"),
+            r#type: row.get::<String, _>("user_type").into(),
+            is_active: row.get("is_active"),
+            hash: row.get("hash"),
+        }
+    }
+}
+
+impl From<PgRow> for Session {
+    fn from(row: PgRow) -> Self {
+        Session {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            created_at: u64::try_from(row.get::<i64, _>("created_at")).unwrap(),
+            last_seen_at: u64::try_from(row.get::<i64, _>("last_seen_at")).unwrap(),
+            expires_at: u64::try_from(row.get::<i64, _>("expires_at")).unwrap(),
+        }
+    }
+}
+
+impl From<PgRow> for Team {
+    fn from(row: PgRow) -> Self {
+        Team {
+            id: row.get("id"),
+            name: row.get("name"),
+            active: row.get("is_active"),
+        }
+    }
+}
+
+impl From<PgRow> for Org {
+    fn from(row: PgRow) -> Self {
+        Org {
+            id: row.get("id"),
+            name: row.get("name"),
+            active: row.get("is_active"),
+        }
+    }
+}
+
+impl From<PgRow> for connector::Details {
+    fn from(row: PgRow) -> Self {
+        connector::Details {
+            id: row.get("id"),
+            name: row.get("name"),
+            r#type: row.get::<String, _>("connector_type").into(),
+            connection_string: row.get("connection_string"),
+            settings: row.get::<Json<_>, _>("settings").0,
+            secret_ref: row.get("secret_ref"),
+        }
+    }
+}
+
+impl From<PgRow> for Dataset {
+    fn from(row: PgRow) -> Self {
+        Dataset {
+            id: row.get("id"),
+            name: row.get("name"),
+            provider: row.get("provider"),
+            connector_id: row.get("connector_id"),
+            path: row.get("path"),
+            description: row.get("description"),
+            schema: row.get::<Json<_>, _>("schema").0,
+            tags: row.get::<Json<_>, _>("tags").0,
+            metadata: row
+                .get::<Option<Json<_>>, _>("metadata")
+                .map(|metadata| metadata.0),
+            profile: row
+                .get::<Option<Json<_>>, _>("profile")
+                .map(|profile| profile.0),
+            schema_version: u32::try_from(row.get::<i32, _>("schema_version")).unwrap(),
+            status: row.get::<String, _>("status").into(),
+            last_synced_at: row
+                .get::<Option<i64>, _>("last_synced_at")
+                .map(|last_synced_at| u64::try_from(last_synced_at).unwrap()),
+        }
+    }
+}
+
+impl From<PgRow> for SchemaVersion {
+    fn from(row: PgRow) -> Self {
+        SchemaVersion {
+            dataset_id: row.get("dataset_id"),
+            version: u32::try_from(row.get::<i32, _>("version")).unwrap(),
+            schema: row.get::<Json<_>, _>("schema").0,
+            changes: row.get::<Json<_>, _>("changes").0,
+            recorded_at: u64::try_from(row.get::<i64, _>("recorded_at")).unwrap(),
+        }
+    }
+}
+
+impl From<PgRow> for ApiKey {
+    fn from(row: PgRow) -> Self {
+        ApiKey {
+            id: row.get("id"),
+            name: row.get("name"),
+            user_id: row.get("user_id"),
+            hash: row.get("hash"),
+            scopes: row.get::<Json<_>, _>("scopes").0,
+            created_at: u64::try_from(row.get::<i64, _>("created_at")).unwrap(),
+            expires_at: row
+                .get::<Option<i64>, _>("expires_at")
+                .map(|expires_at| u64::try_from(expires_at).unwrap()),
+            last_used_at: row
+                .get::<Option<i64>, _>("last_used_at")
+                .map(|last_used_at| u64::try_from(last_used_at).unwrap()),
+        }
+    }
+}
+
+impl From<PgRow> for Identity {
+    fn from(row: PgRow) -> Self {
+        Identity {
+            issuer: row.get("issuer"),
+            subject: row.get("subject"),
+            user_id: row.get("user_id"),
+        }
+    }
+}
@@ -0,0 +1,4 @@
+mod config;
+mod conversions;
+
+pub use config::*;
@@ -6,71 +6,219 @@ use axum::{
     http::StatusCode,
     middleware,
     response::IntoResponse,
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use data::Database;
-use std::collections::HashMap;
 use std::env;
-use std::sync::Arc;
+use std::time::Duration;
 use tower::ServiceBuilder;
 use tower_http::trace::{self, TraceLayer};
-use tracing::{info, Level};
+use tracing::{error, info, Level};
 
-use crate::core::{auth, Connector};
-use crate::data::Dynamodb;
+use crate::core::{
+    auth, Dataset, Envelope, Oidc, Permission, Registry, Secrets, Session, SessionPolicy,
+};
+use crate::data::{Dynamodb, Memory, Postgres};
 
-// TODO: add connections property. It will store list of
-// connections/data sources which are updated during runtime
 #[derive(Debug, Clone)]
 pub struct AppState<D: Database> {
     db: D,
-    connections: Arc<HashMap<String, Connector>>,
+    connections: Registry,
+    envelope: Envelope,
+    session_policy: SessionPolicy,
+    // Single sign-on, when configured
+    oidc: Option<Oidc>,
 }
 
+// How often expired sessions are purged, in seconds
+const SESSION_PURGE_INTERVAL: u64 = 10 * 60;
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    let dynamodb_endpoint = env::var("DYNAMODB_ENDPOINT").ok();
-    let table_name = env::var("TABLE_NAME").unwrap();
+    let database = env::var("DATABASE").unwrap_or_else(|_| String::from("dynamodb"));
+    info!("database: {database}");
 
-    info!("table_name: {table_name}");
-    info!("dynamodb_endpoint: {dynamodb_endpoint:?}");
+    match database.as_str() {
+        "dynamodb" => {
+            let dynamodb_endpoint = env::var("DYNAMODB_ENDPOINT").ok();
+            let table_name = env::var("TABLE_NAME").unwrap();
 
-    let database = Dynamodb::new(dynamodb_endpoint.as_deref(), &table_name)
+            info!("table_name: {table_name}");
+            info!("dynamodb_endpoint: {dynamodb_endpoint:?}");
+
+            let database = Dynamodb::new(dynamodb_endpoint.as_deref(), &table_name)
+                .await
+                .unwrap();
+            serve(database).await;
+        }
+        "postgres" => {
+            let database_url = env::var("DATABASE_URL").unwrap();
+            let database = Postgres::new(&database_url).await.unwrap();
+            serve(database).await;
+        }
+        "memory" => serve(Memory::new().await.unwrap()).await,
+        _ => panic!("Invalid database: {database}"),
+    }
+}
+
+async fn serve<D: Database>(database: D) {
+    let envelope = Envelope::from_env().unwrap();
+    let secrets = Secrets::from_env().unwrap();
+    let session_policy = SessionPolicy::from_env().unwrap();
+    let oidc = Oidc::from_env().unwrap();
+    let connections = Registry::load(database.clone(), envelope.clone(), secrets)
         .await
         .unwrap();
-    let connections = Arc::new(
-        Connector::create_connectors(database.clone())
-            .await
-            .unwrap(),
-    );
     let state = AppState {
         db: database,
         connections,
+        envelope,
+        session_policy,
+        oidc,
     };
 
+    let purge_db = state.db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SESSION_PURGE_INTERVAL));
+        loop {
+            interval.tick().await;
+            match Session::purge_expired(purge_db.clone()).await {
+                Ok(0) => {}
+                Ok(purged) => info!("purged {purged} expired sessions."),
+                Err(e) => error!("session purge failed: {e}"),
+            }
+        }
+    });
+
+    if let Ok(interval) = env::var("SCHEMA_SYNC_INTERVAL_SECS") {
+        let period = Duration::from_secs(interval.parse().unwrap());
+        let sync_state = state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                info!("syncing dataset schemas.");
+                if let Err(e) = Dataset::sync_all(sync_state.clone()).await {
+                    error!("dataset schema sync failed: {e}");
+                }
+            }
+        });
+    }
+
+    // Routes below `auth` that need more than a session
+    let requires = |permission| middleware::from_fn_with_state(permission, auth::require);
+
     let app = Router::new()
         .route("/logout", post(routes::auth::logout))
         .route("/profile", get(routes::user::profile))
-        .route("/users", post(routes::user::create))
-        .route("/orgs", post(routes::org::create))
-        .route("/orgs/:org_id", get(routes::org::get))
-        .route("/orgs/:org_id", delete(routes::org::delete))
-        .route("/teams", post(routes::team::create))
-        .route("/teams/:team_id", get(routes::team::get))
-        .route("/connectors", post(routes::connector::create))
-        .route("/connectors", get(routes::connector::get))
+        .route(
+            "/users",
+            post(routes::user::create).layer(requires(Permission::UsersAdmin)),
+        )
+        .route(
+            "/users/:user_id/role",
+            put(routes::user::assign_role).layer(requires(Permission::UsersAdmin)),
+        )
+        .route(
+            "/orgs",
+            post(routes::org::create).layer(requires(Permission::PlatformAdmin)),
+        )
+        .route(
+            "/orgs/:org_id",
+            get(routes::org::get).layer(requires(Permission::PlatformAdmin)),
+        )
+        .route(
+            "/orgs/:org_id",
+            delete(routes::org::delete).layer(requires(Permission::PlatformAdmin)),
+        )
+        .route(
+            "/teams",
+            post(routes::team::create).layer(requires(Permission::TeamsAdmin)),
+        )
+        .route(
+            "/teams/:team_id",
+            get(routes::team::get).layer(requires(Permission::TeamsAdmin)),
+        )
+        .route(
+            "/service-accounts",
+            post(routes::apikey::create_service_account)
+                .layer(requires(Permission::ServiceAccountsAdmin)),
+        )
+        .route("/api-keys", post(routes::apikey::create))
+        .route("/api-keys", get(routes::apikey::get))
+        .route("/api-keys/:key_id", delete(routes::apikey::delete))
+        .route(
+            "/connectors",
+            post(routes::connector::create).layer(requires(Permission::ConnectorsAdmin)),
+        )
+        .route(
+            "/connectors",
+            get(routes::connector::get).layer(requires(Permission::ConnectorsAdmin)),
+        )
+        .route(
+            "/connectors/test",
+            post(routes::connector::test).layer(requires(Permission::ConnectorsAdmin)),
+        )
+        .route(
+            "/connectors/reencrypt",
+            post(routes::connector::reencrypt).layer(requires(Permission::PlatformAdmin)),
+        )
+        .route(
+            "/connectors/:conn_id",
+            patch(routes::connector::update).layer(requires(Permission::ConnectorsAdmin)),
+        )
+        .route(
+            "/connectors/:conn_id",
+            delete(routes::connector::delete).layer(requires(Permission::ConnectorsAdmin)),
+        )
+        .route(
+            "/connectors/:conn_id/test",
+            post(routes::connector::test_existing).layer(requires(Permission::ConnectorsAdmin)),
+        )
         .route(
             "/connectors/:conn_id/datasets",
-            get(routes::connector::all_datasets),
+            get(routes::connector::all_datasets).layer(requires(Permission::ConnectorsAdmin)),
+        )
+        .route(
+            "/connectors/:conn_id/query",
+            post(routes::connector::query).layer(requires(Permission::ConnectorsAdmin)),
+        )
+        .route(
+            "/datasets",
+            get(routes::dataset::get).layer(requires(Permission::DatasetsRead)),
+        )
+        .route(
+            "/dataset",
+            post(routes::dataset::create).layer(requires(Permission::DatasetsWrite)),
+        )
+        .route(
+            "/datasets/:dataset_id/query",
+            post(routes::dataset::query).layer(requires(Permission::QueryRun)),
+        )
+        .route(
+            "/datasets/:dataset_id/preview",
+            get(routes::dataset::preview).layer(requires(Permission::DatasetsRead)),
+        )
+        .route(
+            "/datasets/:dataset_id/profile",
+            post(routes::dataset::profile).layer(requires(Permission::DatasetsWrite)),
+        )
+        .route(
+            "/datasets/:dataset_id/sync",
+            post(routes::dataset::sync).layer(requires(Permission::DatasetsWrite)),
+        )
+        .route(
+            "/datasets/:dataset_id/schema-history",
+            get(routes::dataset::schema_history).layer(requires(Permission::DatasetsRead)),
         )
-        .route("/datasets", get(routes::dataset::get))
-        .route("/dataset", post(routes::dataset::create))
         .layer(ServiceBuilder::new().layer(middleware::from_fn_with_state(state.clone(), auth)))
         .route("/login", post(routes::auth::login))
         .route("/anonymouslogin", post(routes::auth::anonymous_login))
+        .route("/oidc/login", get(routes::oidc::login))
+        .route("/oidc/callback", get(routes::oidc::callback))
         .route("/health", get(health))
         .with_state(state)
         .layer(
@@ -87,3 +235,18 @@ async fn main() {
 async fn health() -> impl IntoResponse {
     (StatusCode::OK, "healthy").into_response()
 }
+
+#[cfg(test)]
+impl AppState<Memory> {
+    // Empty in-memory state, without connectors
+    fn in_memory() -> Self {
+        let envelope = Envelope::default();
+        AppState {
+            db: Memory::default(),
+            connections: Registry::new(envelope.clone(), Secrets::default()),
+            envelope,
+            session_policy: SessionPolicy::default(),
+            oidc: None,
+        }
+    }
+}
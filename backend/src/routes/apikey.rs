@@ -0,0 +1,123 @@
+use crate::core::apikey::{self, ApiKey};
+use crate::core::user::{self, CreateServiceAccount};
+use crate::core::{Permission, Profile, Role, User};
+use crate::data::Database;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query as QueryParams, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde_json::json;
+
+// Resolves the service account named by an admin, or the caller's own user
+async fn key_owner<D: Database>(
+    state: &AppState<D>,
+    user: User,
+    service_account_id: Option<&str>,
+) -> Result<User, (StatusCode, &'static str)> {
+    let Some(service_account_id) = service_account_id else {
+        return Ok(user);
+    };
+    if !user.r#type.permits(Permission::ServiceAccountsAdmin) {
+        return Err((StatusCode::FORBIDDEN, "FORBIDDEN"));
+    }
+    match User::from_id(state.db.clone(), service_account_id).await {
+        Ok(owner) if owner.r#type == Role::ServiceAccount => Ok(owner),
+        _ => Err((StatusCode::NOT_FOUND, "service account not found")),
+    }
+}
+
+pub async fn create<D: Database>(
+    State(state): State<AppState<D>>,
+    Extension(user_ext): Extension<user::Extension>,
+    Json(payload): Json<apikey::Create>,
+) -> impl IntoResponse {
+    let Some(user) = user_ext.user else {
+        return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
+    };
+    let owner = match key_owner(&state, user, payload.service_account_id.as_deref()).await {
+        Ok(owner) => owner,
+        Err((status, message)) => return (status, Json(json!(message))).into_response(),
+    };
+
+    match ApiKey::create(state.db, &owner, &payload).await {
+        Ok(created) => (StatusCode::OK, Json(json!(created))).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!(e.to_string()))).into_response(),
+    }
+}
+
+pub async fn get<D: Database>(
+    State(state): State<AppState<D>>,
+    Extension(user_ext): Extension<user::Extension>,
+    QueryParams(params): QueryParams<apikey::List>,
+) -> impl IntoResponse {
+    let Some(user) = user_ext.user else {
+        return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
+    };
+    let owner = match key_owner(&state, user, params.service_account_id.as_deref()).await {
+        Ok(owner) => owner,
+        Err((status, message)) => return (status, Json(json!(message))).into_response(),
+    };
+
+    match ApiKey::for_user(state.db, &owner.id).await {
+        Ok(api_keys) => (StatusCode::OK, Json(json!(api_keys))).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn delete<D: Database>(
+    State(state): State<AppState<D>>,
+    Extension(user_ext): Extension<user::Extension>,
+    Path(key_id): Path<String>,
+) -> impl IntoResponse {
+    let Some(user) = user_ext.user else {
+        return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
+    };
+
+    // Keys of other users are reported as missing
+    match ApiKey::from_id(state.db.clone(), &key_id).await {
+        Ok(api_key)
+            if api_key.user_id == user.id
+                || user.r#type.permits(Permission::ServiceAccountsAdmin) => {}
+        _ => return (StatusCode::NOT_FOUND, Json(json!("api key not found"))).into_response(),
+    }
+
+    match ApiKey::delete(state.db, &key_id).await {
+        Ok(()) => (StatusCode::OK, Json(json!("DELETED"))).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn create_service_account<D: Database>(
+    State(state): State<AppState<D>>,
+    Json(payload): Json<CreateServiceAccount>,
+) -> impl IntoResponse {
+    if payload.name.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!("name must not be empty")),
+        )
+            .into_response();
+    }
+
+    match User::create_service_account(state.db, payload.name.trim()).await {
+        Ok(service_account) => {
+            (StatusCode::OK, Json(json!(Profile::from(service_account)))).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
@@ -1,9 +1,16 @@
-use crate::core::{Session, User};
+use crate::core::auth::{add_session_cookies, remove_session_cookies};
+use crate::core::{Session, SessionPolicy, User};
 use crate::data::Database;
 use crate::AppState;
 
 use argon2::{password_hash::PasswordHash, Argon2, PasswordVerifier};
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use axum_extra::extract::cookie::CookieJar;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
@@ -18,8 +25,40 @@ pub struct LoginResponse {
     token: String,
 }
 
+/// API clients get the session token in the response body, as before cookies
+/// were supported. The browser UI asks for `?mode=cookie` and gets the session
+/// in an HttpOnly cookie only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Token,
+    Cookie,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LoginMode {
+    #[serde(default)]
+    mode: Mode,
+}
+
+async fn session_response(
+    session: Session,
+    mode: &LoginMode,
+    jar: CookieJar,
+    policy: &SessionPolicy,
+) -> Response {
+    if mode.mode == Mode::Token {
+        return (StatusCode::OK, Json(LoginResponse { token: session.id })).into_response();
+    }
+    let jar = add_session_cookies(jar, &session, policy).await;
+    (StatusCode::OK, jar, "login successful").into_response()
+}
+
 pub async fn login<D: Database>(
     State(state): State<AppState<D>>,
+    Query(mode): Query<LoginMode>,
+    jar: CookieJar,
     Json(payload): Json<LoginRequest>,
 ) -> impl IntoResponse {
     if let Ok(user) = User::from_email(state.db.clone(), &payload.email).await {
@@ -28,39 +67,209 @@ pub async fn login<D: Database>(
                 .verify_password(&payload.password.into_bytes(), &parsed_hash)
                 .is_ok()
             {
-                if let Ok(session) = Session::create(state.db, Some(&user)).await {
-                    return (
-                        StatusCode::OK,
-                        Json(LoginResponse { token: session.id }).into_response(),
-                    );
+                if let Ok(session) =
+                    Session::create(state.db, Some(&user), &state.session_policy).await
+                {
+                    return session_response(session, &mode, jar, &state.session_policy).await;
                 }
             }
         }
-        (StatusCode::UNAUTHORIZED, "auth failed".into_response())
+        (StatusCode::UNAUTHORIZED, "auth failed").into_response()
     } else {
         info!("USER: search failed");
-        (StatusCode::UNAUTHORIZED, "ERROR: AUTH".into_response())
+        (StatusCode::UNAUTHORIZED, "ERROR: AUTH").into_response()
     }
 }
 
-pub async fn anonymous_login<D: Database>(State(state): State<AppState<D>>) -> impl IntoResponse {
-    if let Ok(session) = Session::create(state.db, None).await {
-        (
-            StatusCode::OK,
-            Json(LoginResponse { token: session.id }).into_response(),
-        )
+pub async fn anonymous_login<D: Database>(
+    State(state): State<AppState<D>>,
+    Query(mode): Query<LoginMode>,
+    jar: CookieJar,
+) -> impl IntoResponse {
+    if let Ok(session) = Session::create(state.db, None, &state.session_policy).await {
+        session_response(session, &mode, jar, &state.session_policy).await
     } else {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to create anonymous session".into_response(),
+            "failed to create anonymous session",
         )
+            .into_response()
     }
 }
 
 pub async fn logout<D: Database>(
     State(state): State<AppState<D>>,
     Extension(session): Extension<Session>,
+    jar: CookieJar,
 ) -> impl IntoResponse {
     let _ = Session::delete(state.db, &session.id).await;
-    "logout successful".into_response()
+    (remove_session_cookies(jar), "logout successful").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::auth::{auth, CSRF_COOKIE, CSRF_HEADER, SESSION_COOKIE};
+    use crate::core::{user, Role};
+    use crate::data::Memory;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request};
+    use axum::{middleware, routing::post, Router};
+    use tower::Service;
+
+    async fn app() -> (Router, AppState<Memory>) {
+        let state = AppState::in_memory();
+        let create = user::Create {
+            email: String::from("alice@example.com"),
+            first_name: String::from("Alice"),
+            last_name: String::from("Smith"),
+            r#type: Role::Analyst,
+            password: String::from("password"),
+        };
+        User::create(state.db.clone(), &create).await.unwrap();
+        let app = Router::new()
+            .route("/logout", post(logout))
+            .layer(middleware::from_fn_with_state(state.clone(), auth))
+            .route("/login", post(login))
+            .with_state(state.clone());
+        (app, state)
+    }
+
+    fn post_request(uri: &str, headers: &[(&str, &str)], body: Body) -> Request<Body> {
+        let mut request = Request::post(uri).header(header::CONTENT_TYPE, "application/json");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(body).unwrap()
+    }
+
+    // Logs in, returning the response's token and cookies
+    async fn log_in(
+        app: &Router,
+        uri: &str,
+        password: &str,
+    ) -> (StatusCode, Option<String>, Vec<String>) {
+        let body = serde_json::json!({ "email": "alice@example.com", "password": password });
+        let response = app
+            .clone()
+            .call(post_request(uri, &[], Body::from(body.to_string())))
+            .await
+            .unwrap();
+        let status = response.status();
+        let cookies = response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|value| value.to_str().unwrap().to_string())
+            .collect();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let token = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body["token"].as_str().map(String::from));
+        (status, token, cookies)
+    }
+
+    // The `name=value` part of a Set-Cookie header
+    fn cookie<'a>(cookies: &'a [String], name: &str) -> &'a str {
+        cookies
+            .iter()
+            .find(|cookie| cookie.starts_with(&format!("{name}=")))
+            .map(|cookie| cookie.split(';').next().unwrap())
+            .unwrap()
+    }
+
+    fn cookie_value(cookies: &[String], name: &str) -> String {
+        cookie(cookies, name)
+            .trim_start_matches(&format!("{name}="))
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn logs_in_with_a_session_cookie() {
+        let (app, _) = app().await;
+        let (status, _, _) = log_in(&app, "/login?mode=cookie", "wrong").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, token, cookies) = log_in(&app, "/login?mode=cookie", "password").await;
+        assert_eq!(status, StatusCode::OK);
+        // Page scripts never see the session id
+        assert!(token.is_none());
+        let session_cookie = cookies
+            .iter()
+            .find(|cookie| cookie.starts_with(&format!("{SESSION_COOKIE}=")))
+            .unwrap();
+        assert!(session_cookie.contains("HttpOnly"));
+        assert!(session_cookie.contains("SameSite=Lax"));
+        let csrf_cookie = cookies
+            .iter()
+            .find(|cookie| cookie.starts_with(&format!("{CSRF_COOKIE}=")))
+            .unwrap();
+        assert!(!csrf_cookie.contains("HttpOnly"));
+    }
+
+    #[tokio::test]
+    async fn returns_the_token_by_default() {
+        let (app, state) = app().await;
+        for uri in ["/login", "/login?mode=token"] {
+            let (status, token, cookies) = log_in(&app, uri, "password").await;
+            assert_eq!(status, StatusCode::OK);
+            assert!(cookies.is_empty());
+            let session = Session::from_id(state.db.clone(), &token.unwrap())
+                .await
+                .unwrap();
+            assert!(session.user_id.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_cookie_requests_without_the_csrf_token() {
+        let (app, state) = app().await;
+        let (_, _, cookies) = log_in(&app, "/login?mode=cookie", "password").await;
+        let token = cookie_value(&cookies, SESSION_COOKIE);
+        let cookie_header = format!(
+            "{}; {}",
+            cookie(&cookies, SESSION_COOKIE),
+            cookie(&cookies, CSRF_COOKIE)
+        );
+        let csrf = cookie_value(&cookies, CSRF_COOKIE);
+
+        for headers in [
+            vec![("cookie", cookie_header.as_str())],
+            vec![("cookie", cookie_header.as_str()), (CSRF_HEADER, "forged")],
+        ] {
+            let response = app
+                .clone()
+                .call(post_request("/logout", &headers, Body::empty()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+        assert!(Session::from_id(state.db.clone(), &token).await.is_ok());
+
+        let headers = [("cookie", cookie_header.as_str()), (CSRF_HEADER, &csrf)];
+        let response = app
+            .clone()
+            .call(post_request("/logout", &headers, Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(Session::from_id(state.db.clone(), &token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn accepts_bearer_tokens_without_the_csrf_token() {
+        let (app, state) = app().await;
+        let (_, token, _) = log_in(&app, "/login", "password").await;
+        let token = token.unwrap();
+
+        let authorization = format!("Bearer {token}");
+        let headers = [("authorization", authorization.as_str())];
+        let response = app
+            .clone()
+            .call(post_request("/logout", &headers, Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(Session::from_id(state.db, &token).await.is_err());
+    }
 }
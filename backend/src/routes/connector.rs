@@ -1,58 +1,200 @@
 use crate::core::connector::{self, Trait};
-use crate::core::user;
-use crate::core::PostgresConnector;
+use crate::core::{
+    Envelope, FileConnector, LocalFiles, MySqlConnector, PostgresConnector, S3Connector,
+    SqliteConnector, SECRET_SCHEME,
+};
 use crate::data::Database;
 use crate::AppState;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query as QueryParams, State},
     http::StatusCode,
     response::IntoResponse,
-    Extension, Json,
+    Json,
 };
 use serde_json::json;
 
 pub async fn create<D: Database>(
     State(state): State<AppState<D>>,
-    Extension(user_ext): Extension<user::Extension>,
-    Json(payload): Json<connector::Create>,
+    Json(mut payload): Json<connector::Create>,
 ) -> impl IntoResponse {
-    if let Some(user) = user_ext.user {
-        if user.r#type != "superadmin" {
-            return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
+    if Envelope::is_encrypted(&payload.connection_string) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!("connection string must not be encrypted")),
+        )
+            .into_response();
+    }
+    if let Err(e) = payload.settings.validate(&payload.r#type) {
+        return (StatusCode::BAD_REQUEST, Json(json!(e.to_string()))).into_response();
+    }
+
+    if !payload.force {
+        let details = connector::TestConnection {
+            r#type: payload.r#type.clone(),
+            connection_string: payload.connection_string.clone(),
+            settings: payload.settings.clone(),
+        }
+        .details();
+        let test = state.connections.test_details(&details).await;
+        if !test.ok {
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!(test))).into_response();
         }
-    } else {
-        return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
     }
 
-    match payload.r#type {
-        connector::Type::Postgres => {
-            if let Err(e) = PostgresConnector::create_record(state.db, payload).await {
+    payload.secret_ref = payload.connection_string.starts_with(SECRET_SCHEME);
+    payload.connection_string = match state.envelope.encrypt(&payload.connection_string) {
+        Ok(connection_string) => connection_string,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!(e.to_string())),
+            )
+                .into_response();
+        }
+    };
+
+    let record = match payload.r#type {
+        connector::Type::Postgres => PostgresConnector::create_record(state.db, payload).await,
+        connector::Type::MySql => MySqlConnector::create_record(state.db, payload).await,
+        connector::Type::File => {
+            FileConnector::<LocalFiles>::create_record(state.db, payload).await
+        }
+        connector::Type::S3 => S3Connector::create_record(state.db, payload).await,
+        connector::Type::Sqlite => SqliteConnector::create_record(state.db, payload).await,
+    };
+
+    match record {
+        Ok(details) => {
+            // Connectors that can not connect yet are retried on first use
+            let _ = state.connections.insert(details).await;
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!(e.to_string())),
+            )
+                .into_response();
+        }
+    }
+
+    (StatusCode::OK, Json(json!("CREATED"))).into_response()
+}
+
+pub async fn get<D: Database>(State(state): State<AppState<D>>) -> impl IntoResponse {
+    match connector::Details::get_connector_details(state.db, true).await {
+        Ok(connector_details) => {
+            let summaries = state.connections.summaries(connector_details).await;
+            (StatusCode::OK, Json(json!(summaries))).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn update<D: Database>(
+    State(state): State<AppState<D>>,
+    Path(connector_id): Path<String>,
+    Json(payload): Json<connector::Update>,
+) -> impl IntoResponse {
+    let mut details = match state.db.get_connector_by_id(&connector_id).await {
+        Ok(details) => details,
+        Err(e) => return (StatusCode::NOT_FOUND, Json(json!(e.to_string()))).into_response(),
+    };
+
+    if payload
+        .connection_string
+        .as_deref()
+        .is_some_and(Envelope::is_encrypted)
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!("connection string must not be encrypted")),
+        )
+            .into_response();
+    }
+    if let Some(name) = payload.name {
+        details.name = name;
+    }
+    let reconnect = payload.connection_string.is_some() || payload.settings.is_some();
+    if let Some(settings) = payload.settings {
+        if let Err(e) = settings.validate(&details.r#type) {
+            return (StatusCode::BAD_REQUEST, Json(json!(e.to_string()))).into_response();
+        }
+        details.settings = settings;
+    }
+    if reconnect && !payload.force {
+        let test = match &payload.connection_string {
+            Some(connection_string) => {
+                state
+                    .connections
+                    .test_details(&connector::Details {
+                        connection_string: connection_string.clone(),
+                        ..details.clone()
+                    })
+                    .await
+            }
+            None => state.connections.test_saved(&details).await,
+        };
+        if !test.ok {
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!(test))).into_response();
+        }
+    }
+    if let Some(connection_string) = payload.connection_string {
+        details.secret_ref = connection_string.starts_with(SECRET_SCHEME);
+        details.connection_string = match state.envelope.encrypt(&connection_string) {
+            Ok(connection_string) => connection_string,
+            Err(e) => {
                 return (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     Json(json!(e.to_string())),
                 )
                     .into_response();
             }
-        }
+        };
     }
 
-    (StatusCode::OK, Json(json!("CREATED"))).into_response()
+    match details.update(state).await {
+        Ok(()) => (StatusCode::OK, Json(json!("UPDATED"))).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
 }
 
-pub async fn get<D: Database>(
+pub async fn delete<D: Database>(
     State(state): State<AppState<D>>,
-    Extension(user_ext): Extension<user::Extension>,
+    Path(connector_id): Path<String>,
+    QueryParams(params): QueryParams<connector::Delete>,
 ) -> impl IntoResponse {
-    if let Some(user) = user_ext.user {
-        if user.r#type != "superadmin" {
-            return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
-        }
-    } else {
-        return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
+    if let Err(e) = state.db.get_connector_by_id(&connector_id).await {
+        return (StatusCode::NOT_FOUND, Json(json!(e.to_string()))).into_response();
     }
 
-    match connector::Details::get_connector_details(state.db, true).await {
-        Ok(connector_details) => (StatusCode::OK, Json(json!(connector_details))).into_response(),
+    match connector::Details::delete(state, &connector_id, params.cascade).await {
+        Ok(()) => (StatusCode::OK, Json(json!("DELETED"))).into_response(),
+        Err(e) => match e.downcast_ref::<connector::InUse>() {
+            Some(in_use) => (
+                StatusCode::CONFLICT,
+                Json(json!({ "error": in_use.to_string(), "datasets": in_use.0 })),
+            )
+                .into_response(),
+            None => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!(e.to_string())),
+            )
+                .into_response(),
+        },
+    }
+}
+
+pub async fn reencrypt<D: Database>(State(state): State<AppState<D>>) -> impl IntoResponse {
+    match connector::Details::reencrypt_all(state).await {
+        Ok(changed) => (StatusCode::OK, Json(json!({ "reencrypted": changed }))).into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!(e.to_string())),
@@ -61,24 +203,36 @@ pub async fn get<D: Database>(
     }
 }
 
-pub async fn all_datasets<D: Database>(
+pub async fn test<D: Database>(
+    State(state): State<AppState<D>>,
+    Json(payload): Json<connector::TestConnection>,
+) -> impl IntoResponse {
+    let test = state.connections.test_details(&payload.details()).await;
+    (StatusCode::OK, Json(json!(test))).into_response()
+}
+
+pub async fn test_existing<D: Database>(
     State(state): State<AppState<D>>,
-    Extension(user_ext): Extension<user::Extension>,
     Path(connector_id): Path<String>,
 ) -> impl IntoResponse {
-    if let Some(user) = user_ext.user {
-        if user.r#type != "superadmin" {
-            return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
-        }
-    } else {
-        return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
+    match state.connections.test(&connector_id).await {
+        Ok(test) => (StatusCode::OK, Json(json!(test))).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, Json(json!(e.to_string()))).into_response(),
     }
+}
+
+pub async fn all_datasets<D: Database>(
+    State(state): State<AppState<D>>,
+    Path(connector_id): Path<String>,
+    QueryParams(params): QueryParams<connector::Discover>,
+) -> impl IntoResponse {
+    let connector = match state.connections.get(&connector_id).await {
+        Ok(connector) => connector,
+        Err(e) => return (StatusCode::NOT_FOUND, Json(json!(e.to_string()))).into_response(),
+    };
 
-    match state
-        .connections
-        .get(&connector_id)
-        .unwrap()
-        .get_available_datasets()
+    match connector
+        .get_available_datasets(params.schema.as_deref())
         .await
     {
         Ok(connector_details) => (StatusCode::OK, Json(json!(connector_details))).into_response(),
@@ -89,3 +243,19 @@ pub async fn all_datasets<D: Database>(
             .into_response(),
     }
 }
+
+pub async fn query<D: Database>(
+    State(state): State<AppState<D>>,
+    Path(connector_id): Path<String>,
+    Json(payload): Json<connector::SqlQuery>,
+) -> impl IntoResponse {
+    let connector = match state.connections.get(&connector_id).await {
+        Ok(connector) => connector,
+        Err(e) => return (StatusCode::NOT_FOUND, Json(json!(e.to_string()))).into_response(),
+    };
+
+    match connector.query(&payload).await {
+        Ok(result) => (StatusCode::OK, Json(json!(result))).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!(e.to_string()))).into_response(),
+    }
+}
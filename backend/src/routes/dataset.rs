@@ -1,24 +1,21 @@
 use crate::core::{
-    dataset::{Create, Dataset},
+    dataset::{Create, Dataset, Preview, Refresh},
+    query::Query,
     user,
 };
 use crate::data::Database;
 use crate::AppState;
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
+use axum::{
+    extract::{Path, Query as QueryParams, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
 use serde_json::json;
 
 // TODO: Accept anonymous users with UserExtension
 
-pub async fn get<D: Database>(
-    State(state): State<AppState<D>>,
-    Extension(user_ext): Extension<user::Extension>,
-) -> impl IntoResponse {
-    if let Some(user) = user_ext.user {
-        if user.r#type != "superadmin" {
-            return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
-        }
-    }
-
+pub async fn get<D: Database>(State(state): State<AppState<D>>) -> impl IntoResponse {
     match Dataset::get_all(state.db).await {
         Ok(datasets) => (StatusCode::OK, Json(json!(datasets))).into_response(),
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
@@ -27,17 +24,93 @@ pub async fn get<D: Database>(
 
 pub async fn create<D: Database>(
     State(state): State<AppState<D>>,
-    Extension(user_ext): Extension<user::Extension>,
     Json(payload): Json<Create>,
 ) -> impl IntoResponse {
-    if let Some(user) = user_ext.user {
-        if user.r#type != "superadmin" {
-            return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
-        }
-    }
-
     match Dataset::create(state, payload).await {
         Ok(()) => StatusCode::OK.into_response(),
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     }
 }
+
+pub async fn query<D: Database>(
+    State(state): State<AppState<D>>,
+    Path(dataset_id): Path<String>,
+    Json(payload): Json<Query>,
+) -> impl IntoResponse {
+    let Ok(dataset) = Dataset::from_id(state.db.clone(), &dataset_id).await else {
+        return (StatusCode::NOT_FOUND, Json(json!("Dataset not found"))).into_response();
+    };
+
+    match dataset.query(state, &payload).await {
+        Ok(result) => (StatusCode::OK, Json(json!(result))).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!(e.to_string()))).into_response(),
+    }
+}
+
+pub async fn preview<D: Database>(
+    State(state): State<AppState<D>>,
+    Extension(user_ext): Extension<user::Extension>,
+    Path(dataset_id): Path<String>,
+    QueryParams(params): QueryParams<Preview>,
+) -> impl IntoResponse {
+    let Ok(dataset) = Dataset::from_id(state.db.clone(), &dataset_id).await else {
+        return (StatusCode::NOT_FOUND, Json(json!("Dataset not found"))).into_response();
+    };
+
+    let anonymous = user_ext.user.is_none();
+    match dataset.preview(state, &params, anonymous).await {
+        Ok(result) => (StatusCode::OK, Json(json!(result))).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn profile<D: Database>(
+    State(state): State<AppState<D>>,
+    Path(dataset_id): Path<String>,
+    QueryParams(params): QueryParams<Refresh>,
+) -> impl IntoResponse {
+    let Ok(dataset) = Dataset::from_id(state.db.clone(), &dataset_id).await else {
+        return (StatusCode::NOT_FOUND, Json(json!("Dataset not found"))).into_response();
+    };
+
+    match dataset.profile(state, &params).await {
+        Ok(profile) => (StatusCode::OK, Json(json!(profile))).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn sync<D: Database>(
+    State(state): State<AppState<D>>,
+    Path(dataset_id): Path<String>,
+) -> impl IntoResponse {
+    let Ok(dataset) = Dataset::from_id(state.db.clone(), &dataset_id).await else {
+        return (StatusCode::NOT_FOUND, Json(json!("Dataset not found"))).into_response();
+    };
+
+    match dataset.sync(state).await {
+        Ok(result) => (StatusCode::OK, Json(json!(result))).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn schema_history<D: Database>(
+    State(state): State<AppState<D>>,
+    Path(dataset_id): Path<String>,
+) -> impl IntoResponse {
+    match Dataset::schema_history(state.db, &dataset_id).await {
+        Ok(versions) => (StatusCode::OK, Json(json!(versions))).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -1,6 +1,8 @@
+pub mod apikey;
 pub mod auth;
 pub mod connector;
 pub mod dataset;
+pub mod oidc;
 pub mod org;
 pub mod team;
 pub mod user;
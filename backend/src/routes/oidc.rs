@@ -0,0 +1,105 @@
+use crate::core::auth::add_session_cookies;
+use crate::core::oidc::{Callback, Pending};
+use crate::core::Session;
+use crate::data::Database;
+use crate::AppState;
+use axum::{
+    extract::{Query as QueryParams, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+    Json,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use cookie::time::Duration;
+use serde_json::json;
+use tracing::{error, info};
+
+// Holds the login's state, nonce and PKCE verifier until the callback
+const OIDC_COOKIE: &str = "oidc";
+
+pub async fn login<D: Database>(
+    State(state): State<AppState<D>>,
+    jar: CookieJar,
+) -> impl IntoResponse {
+    let Some(oidc) = &state.oidc else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!("single sign-on is not configured")),
+        )
+            .into_response();
+    };
+
+    match oidc.begin().await {
+        Ok((url, pending)) => {
+            // Lax, as the provider redirects back from another site
+            let cookie = Cookie::build((OIDC_COOKIE, pending.encode()))
+                .path("/oidc")
+                .http_only(true)
+                .secure(state.session_policy.secure_cookies)
+                .same_site(SameSite::Lax)
+                .max_age(Duration::minutes(10));
+            (jar.add(cookie), Redirect::to(url.as_str())).into_response()
+        }
+        Err(e) => {
+            error!("single sign-on: {e}");
+            (StatusCode::BAD_GATEWAY, Json(json!(e.to_string()))).into_response()
+        }
+    }
+}
+
+pub async fn callback<D: Database>(
+    State(state): State<AppState<D>>,
+    jar: CookieJar,
+    QueryParams(params): QueryParams<Callback>,
+) -> impl IntoResponse {
+    let Some(oidc) = &state.oidc else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!("single sign-on is not configured")),
+        )
+            .into_response();
+    };
+
+    let pending = jar
+        .get(OIDC_COOKIE)
+        .and_then(|cookie| Pending::decode(cookie.value()));
+    let jar = jar.remove(Cookie::build(OIDC_COOKIE).path("/oidc"));
+    if let Some(error) = params.error {
+        let description = params.error_description.unwrap_or_default();
+        info!("single sign-on refused: {error} {description}");
+        return (StatusCode::UNAUTHORIZED, jar, Json(json!(error))).into_response();
+    }
+    let (Some(pending), Some(code), Some(returned_state)) = (pending, params.code, params.state)
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            jar,
+            Json(json!("no single sign-on login in progress")),
+        )
+            .into_response();
+    };
+
+    let user = match oidc
+        .complete(state.db.clone(), &code, &returned_state, pending)
+        .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            info!("single sign-on failed: {e}");
+            return (StatusCode::UNAUTHORIZED, jar, Json(json!(e.to_string()))).into_response();
+        }
+    };
+
+    match Session::create(state.db, Some(&user), &state.session_policy).await {
+        Ok(session) => {
+            let jar = add_session_cookies(jar, &session, &state.session_policy).await;
+            (jar, Redirect::to(&oidc.post_login_url)).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            jar,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
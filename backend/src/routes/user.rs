@@ -1,34 +1,169 @@
 use crate::core::{
-    user::{self, User},
-    Profile,
+    role,
+    user::{self, EmailTaken, User},
+    Profile, Role,
 };
 use crate::data::Database;
 use crate::AppState;
 use axum::{
-    debug_handler, extract::State, http::StatusCode, response::IntoResponse, Extension, Json,
+    debug_handler,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
 };
+use serde_json::json;
 
 #[debug_handler]
 pub async fn profile(Extension(user_ext): Extension<user::Extension>) -> impl IntoResponse {
     Json(user_ext.user.map(Profile::from)).into_response()
 }
 
+// Admins can only grant roles that do not exceed their own. Service accounts
+// are created with `POST /service-accounts` and keep their role.
+fn grantable(admin: &User, role: Role) -> bool {
+    role != Role::ServiceAccount && admin.r#type.includes(role)
+}
+
 pub async fn create<D: Database>(
     State(state): State<AppState<D>>,
-    Extension(user): Extension<User>,
+    Extension(user_ext): Extension<user::Extension>,
     Json(payload): Json<user::Create>,
 ) -> impl IntoResponse {
-    match user.r#type.as_str() {
-        "superadmin" => {
-            println!("creating user.");
-            if let Ok(()) = User::create(state.db, &payload).await {
-                println!("user created successfully");
-                (StatusCode::OK, "user created")
-            } else {
-                println!("user creation failed");
-                (StatusCode::INTERNAL_SERVER_ERROR, "Error")
-            }
+    let Some(user) = user_ext.user else {
+        return (StatusCode::UNAUTHORIZED, "UNAUTHORIZED");
+    };
+    if !grantable(&user, payload.r#type) {
+        return (StatusCode::FORBIDDEN, "role not permitted");
+    }
+
+    println!("creating user.");
+    match User::create(state.db, &payload).await {
+        Ok(()) => {
+            println!("user created successfully");
+            (StatusCode::OK, "user created")
+        }
+        Err(e) if e.is::<EmailTaken>() => (StatusCode::CONFLICT, "email already in use"),
+        Err(_) => {
+            println!("user creation failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error")
+        }
+    }
+}
+
+pub async fn assign_role<D: Database>(
+    State(state): State<AppState<D>>,
+    Extension(user_ext): Extension<user::Extension>,
+    Path(user_id): Path<String>,
+    Json(payload): Json<role::Assign>,
+) -> impl IntoResponse {
+    let Some(user) = user_ext.user else {
+        return (StatusCode::UNAUTHORIZED, Json(json!("UNAUTHORIZED"))).into_response();
+    };
+    if user_id == user.id {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!("can not change your own role")),
+        )
+            .into_response();
+    }
+    if !grantable(&user, payload.role) {
+        return (StatusCode::FORBIDDEN, Json(json!("role not permitted"))).into_response();
+    }
+
+    match User::from_id(state.db.clone(), &user_id).await {
+        Ok(target) if grantable(&user, target.r#type) => {}
+        Ok(_) => return (StatusCode::FORBIDDEN, Json(json!("user not permitted"))).into_response(),
+        Err(_) => return (StatusCode::NOT_FOUND, Json(json!("user not found"))).into_response(),
+    }
+
+    match User::set_role(state.db, &user_id, payload.role).await {
+        Ok(target) => (StatusCode::OK, Json(json!(Profile::from(target)))).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!(e.to_string())),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::UserStore;
+
+    fn user(role: Role) -> User {
+        User {
+            id: String::from("admin"),
+            email: String::from("admin@example.com"),
+            first_name: String::from("Admin"),
+            last_name: String::new(),
+            r#type: role,
+            is_active: true,
+            hash: String::new(),
         }
-        _ => (StatusCode::UNAUTHORIZED, "user creation not permitted"),
+    }
+
+    #[test]
+    fn grants_roles_up_to_the_admins_own() {
+        let org_admin = user(Role::OrgAdmin);
+        assert!(grantable(&org_admin, Role::Viewer));
+        assert!(grantable(&org_admin, Role::DataSteward));
+        assert!(grantable(&org_admin, Role::OrgAdmin));
+        assert!(!grantable(&org_admin, Role::PlatformAdmin));
+
+        let platform_admin = user(Role::PlatformAdmin);
+        assert!(grantable(&platform_admin, Role::PlatformAdmin));
+        // Only created as service accounts
+        assert!(!grantable(&platform_admin, Role::ServiceAccount));
+    }
+
+    #[tokio::test]
+    async fn rejects_emails_already_in_use() {
+        let state = AppState::in_memory();
+        let admin = user(Role::PlatformAdmin);
+        state.db.create_user(&admin).await.unwrap();
+        let payload = |email: &str| user::Create {
+            email: email.to_string(),
+            first_name: String::from("Mallory"),
+            last_name: String::new(),
+            r#type: Role::Viewer,
+            password: String::from("password"),
+        };
+        let extension = || {
+            Extension(user::Extension {
+                user: Some(user(Role::OrgAdmin)),
+            })
+        };
+
+        let response = create(
+            State(state.clone()),
+            extension(),
+            Json(payload("admin@example.com")),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let owner = User::from_email(state.db.clone(), "admin@example.com")
+            .await
+            .unwrap();
+        assert_eq!(owner.id, admin.id);
+
+        // The store refuses it too, if another request got there first
+        let taken = User {
+            id: String::from("other"),
+            ..admin
+        };
+        let error = state.db.create_user(&taken).await.unwrap_err();
+        assert!(error.is::<EmailTaken>());
+
+        let response = create(
+            State(state.clone()),
+            extension(),
+            Json(payload("new@example.com")),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
     }
 }
@@ -1,9 +1,28 @@
 "use client"
 
-import { ReactNode, useState } from 'react';
+import { useState } from 'react';
 import { motion, AnimatePresence } from 'framer-motion';
 import { ChevronDownIcon } from '@heroicons/react/24/solid';
 
+interface ForeignKey {
+  table: string;
+  column: string;
+}
+
+interface Column {
+  name: string;
+  data_type: string;
+  nullable: boolean;
+  default?: string | null;
+  primary_key: boolean;
+  foreign_key?: ForeignKey | null;
+  comment?: string | null;
+}
+
+interface Schema {
+  columns: Column[];
+}
+
 interface DataInfo {
   name: string;
   provider: string;
@@ -13,7 +32,7 @@ interface DataInfo {
   metadata: Record<string, any>;
   path: string;
   tags: string[];
-  schema: Record<string, any>;
+  schema: Schema;
 }
 
 interface CustomAccordionProps {
@@ -65,8 +84,13 @@ const CustomAccordion = ({ data_info }: CustomAccordionProps) => {
                 <div>
                   <p><strong><u>Schema:</u></strong></p>
                   <ul className="list-disc pl-6">
-                    {Object.entries(data_info.schema).map(([key, value]) => (
-                      <li key={key}><strong>{key}:</strong> {value as ReactNode}</li>
+                    {data_info.schema.columns.map((column) => (
+                      <li key={column.name}>
+                        <strong>{column.name}:</strong> {column.data_type}
+                        {column.primary_key && ' (primary key)'}
+                        {!column.nullable && ' not null'}
+                        {column.foreign_key && ` → ${column.foreign_key.table}.${column.foreign_key.column}`}
+                      </li>
                     ))}
                   </ul>
                 </div>
@@ -43,7 +43,12 @@ const exampleDataset = {
   metadata: {"requested_by": "someone"},
   path: "warehouse.data",
   tags: ["world"],
-  schema: {"planet": "string", "value": "number"}
+  schema: {
+    columns: [
+      {name: "planet", data_type: "text", nullable: false, primary_key: true},
+      {name: "value", data_type: "bigint", nullable: true, primary_key: false},
+    ]
+  }
 }
 
 export default async function Data() {